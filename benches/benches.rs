@@ -7,37 +7,37 @@ use std::collections::{ BTreeMap, HashMap };
 
 const SEED: u64 = 59;
 
-fn art_insert(art: &mut ARTree<u64, u64>, keys: &Vec<u64>) {
+fn art_insert(art: &mut ARTree<u64, u64>, keys: &[u64]) {
     for key in keys.iter() {
         art.insert(*key, *key + 1);
     }
 }
 
-fn hmap_insert(hmap: &mut HashMap<u64, u64>, keys: &Vec<u64> ) {
+fn hmap_insert(hmap: &mut HashMap<u64, u64>, keys: &[u64]) {
     for key in keys.iter() {
         hmap.insert(*key, *key + 1);
     }
 }
 
-fn btree_insert(btree: &mut BTreeMap<u64, u64>, keys: &Vec<u64>) {
+fn btree_insert(btree: &mut BTreeMap<u64, u64>, keys: &[u64]) {
     for key in keys.iter() {
         btree.insert(*key, *key + 1);
     }
 }
 
-fn art_get(art: &mut ARTree<u64, u64>, keys: &Vec<u64>) {
+fn art_get(art: &mut ARTree<u64, u64>, keys: &[u64]) {
     for key in keys.iter() {
-        assert_eq!(*key + 1, *art.get(*key).unwrap());
+        assert_eq!(*key + 1, *art.get(key).unwrap());
     }
 }
 
-fn hmap_get(hmap: &mut HashMap<u64, u64>, keys: &Vec<u64> ) {
+fn hmap_get(hmap: &mut HashMap<u64, u64>, keys: &[u64]) {
     for key in keys.iter() {
         assert_eq!(*key + 1, *hmap.get(key).unwrap());
     }
 }
 
-fn btree_get(btree: &mut BTreeMap<u64, u64>, keys: &Vec<u64>) {
+fn btree_get(btree: &mut BTreeMap<u64, u64>, keys: &[u64]) {
     for key in keys.iter() {
         assert_eq!(*key + 1, *btree.get(key).unwrap());
     }
@@ -91,5 +91,25 @@ fn bench_gets(c: &mut Criterion) {
     group.finish();
 }
 
-criterion_group!(benches, bench_inserts, bench_gets);
+fn bench_sorted_loads(c: &mut Criterion) {
+    let mut rng = Pcg64::seed_from_u64(SEED);
+
+    let mut group = c.benchmark_group("Sorted loads");
+    for i in (200_000..4_000_001).step_by(200_000) {
+        let mut keys: Vec<u64> = vec![0; i];
+        rng.fill(&mut keys[..]);
+        keys.sort_unstable();
+        keys.dedup();
+
+        group.bench_with_input(BenchmarkId::new("ART insert", i), &keys,
+            |b, k| b.iter(|| art_insert(&mut ARTree::new(), k)));
+        group.bench_with_input(BenchmarkId::new("ART from_sorted_iter", i), &keys,
+            |b, k| b.iter(|| {
+                ARTree::from_sorted_iter(k.iter().map(|&key| (key, key + 1))).unwrap()
+            }));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_inserts, bench_gets, bench_sorted_loads);
 criterion_main!(benches);
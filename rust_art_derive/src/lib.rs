@@ -0,0 +1,94 @@
+//! Derive macros for `rust_art::keys::ARTKey` and `rust_art::keys::ARTKeyDecode`.
+//!
+//! Fields are encoded as composite key components in declaration order, which
+//! matches the order produced by `#[derive(PartialOrd, Ord)]`.
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, parse_quote, Data, DeriveInput, Error, Fields, Generics, Ident};
+
+#[proc_macro_derive(ARTKey)]
+pub fn derive_art_key(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_art_key(input).unwrap_or_else(Error::into_compile_error).into()
+}
+
+#[proc_macro_derive(ARTKeyDecode)]
+pub fn derive_art_key_decode(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_art_key_decode(input).unwrap_or_else(Error::into_compile_error).into()
+}
+
+fn struct_fields(input: &DeriveInput) -> syn::Result<&Fields> {
+    match &input.data {
+        Data::Struct(data) => Ok(&data.fields),
+        _ => Err(Error::new(Span::call_site(), "ARTKey can only be derived for structs")),
+    }
+}
+
+fn add_bounds(mut generics: Generics, bound: TokenStream2) -> Generics {
+    for param in generics.type_params_mut() {
+        param.bounds.push(parse_quote!(#bound));
+    }
+    generics
+}
+
+fn expand_art_key(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let fields = struct_fields(&input)?;
+    let generics = add_bounds(input.generics.clone(), quote!(::rust_art::keys::ARTKey));
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let members: Vec<_> = fields.members().collect();
+
+    Ok(quote! {
+        impl #impl_generics ::rust_art::keys::ARTKey for #name #ty_generics #where_clause {
+            type Bytes<'__art> = ::std::vec::Vec<u8> where Self: '__art;
+
+            fn convert_to_bytes(&self) -> Self::Bytes<'_> {
+                let mut bytes = ::std::vec::Vec::new();
+                ::rust_art::keys::ARTKey::encode_component(self, &mut bytes);
+                bytes
+            }
+
+            fn encode_component(&self, out: &mut ::std::vec::Vec<u8>) {
+                #(::rust_art::keys::ARTKey::encode_component(&self.#members, out);)*
+            }
+        }
+    })
+}
+
+fn expand_art_key_decode(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let fields = struct_fields(&input)?;
+    let generics = add_bounds(input.generics.clone(), quote!(::rust_art::keys::ARTKeyDecode));
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let vars: Vec<Ident> = (0..fields.len()).map(|i| format_ident!("__field{}", i)).collect();
+    let types = fields.iter().map(|field| &field.ty);
+    let construct = match fields {
+        Fields::Named(named) => {
+            let idents = named.named.iter().map(|field| &field.ident);
+            quote!(#name { #(#idents: #vars),* })
+        }
+        Fields::Unnamed(_) => quote!(#name(#(#vars),*)),
+        Fields::Unit => quote!(#name),
+    };
+
+    Ok(quote! {
+        impl #impl_generics ::rust_art::keys::ARTKeyDecode for #name #ty_generics #where_clause {
+            fn convert_from_bytes(bytes: &[u8]) -> Self {
+                let (key, rest) = <Self as ::rust_art::keys::ARTKeyDecode>::decode_component(bytes);
+                assert!(rest.is_empty(), "invalid key length");
+                key
+            }
+
+            fn decode_component(bytes: &[u8]) -> (Self, &[u8]) {
+                #(let (#vars, bytes) =
+                    <#types as ::rust_art::keys::ARTKeyDecode>::decode_component(bytes);)*
+                (#construct, bytes)
+            }
+        }
+    })
+}
@@ -0,0 +1,337 @@
+use crate::iter::RawIter;
+use crate::keys::{ARTKey, ByteKey};
+use crate::node::{ARTInnerNode, ARTLeaf, ARTLink, ARTNode, InnerNode};
+use crate::ARTree;
+
+use std::cmp::Ordering;
+use std::error::Error;
+use std::fmt;
+
+/// Why `ARTree::from_sorted_iter` or `ARTree::bulk_load` rejected its input.
+/// `index` counts the input entries that came before the offending one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BulkLoadError {
+    /// The entry's key sorts before the previous key.
+    Unsorted { index: usize },
+    /// The entry's key equals the previous key.
+    Duplicate { index: usize },
+}
+
+impl fmt::Display for BulkLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BulkLoadError::Unsorted { index } => {
+                write!(f, "entry {} sorts before the previous key", index)
+            }
+            BulkLoadError::Duplicate { index } => {
+                write!(f, "entry {} repeats the previous key", index)
+            }
+        }
+    }
+}
+
+impl Error for BulkLoadError {}
+
+/// An inner node on the rightmost path of the tree being built.
+struct Frame<V> {
+    /// The node's full key, i.e. its path followed by its compressed prefix.
+    key: ByteKey,
+    value: Option<V>,
+    children: Vec<(u8, ARTNode<V>)>,
+}
+
+/// A finished subtree whose compressed prefix is only known once it is
+/// attached to its parent.
+enum Subtree<V> {
+    Leaf(ByteKey, V),
+    Inner(Frame<V>),
+}
+
+impl<V> Subtree<V> {
+    fn full_key(&self) -> &[u8] {
+        match self {
+            Subtree::Leaf(key, _) => key,
+            Subtree::Inner(frame) => &frame.key,
+        }
+    }
+
+    /// Turns the subtree into a node whose prefix starts at `depth`.
+    fn into_node(self, depth: usize) -> ARTNode<V> {
+        match self {
+            Subtree::Leaf(mut key, value) => {
+                ARTNode::Leaf(ARTLeaf::new(key.split_off(depth), value))
+            }
+            Subtree::Inner(Frame { mut key, value, children }) => {
+                let mut inner = ARTInnerNode::with_capacity(children.len());
+                for (key_byte, child) in children {
+                    inner.add_node(child, key_byte);
+                }
+                ARTNode::Inner(inner, key.split_off(depth), value)
+            }
+        }
+    }
+}
+
+/// Builds a tree from strictly ascending encoded keys in a single pass.
+///
+/// Only the rightmost path is kept open. Every key closes the open nodes it
+/// no longer shares a prefix with, so each inner node is created once, at its
+/// final size and with its final prefix.
+pub(crate) struct SortedBuilder<V> {
+    stack: Vec<Frame<V>>,
+    last: Option<(ByteKey, V)>,
+    len: usize,
+}
+
+impl<V> SortedBuilder<V> {
+    pub(crate) fn new() -> Self {
+        SortedBuilder {
+            stack: Vec::new(),
+            last: None,
+            len: 0,
+        }
+    }
+
+    /// Adds the next entry, or hands the value back along with how `key`
+    /// compares to the previous key if it does not sort strictly after it.
+    pub(crate) fn push(&mut self, key: ByteKey, value: V) -> Result<(), (Ordering, V)> {
+        let (last_key, last_value) = match self.last.take() {
+            Some(last) => last,
+            None => {
+                self.last = Some((key, value));
+                self.len += 1;
+                return Ok(());
+            }
+        };
+
+        match key.cmp(&last_key) {
+            Ordering::Greater => {}
+            order => {
+                self.last = Some((last_key, last_value));
+                return Err((order, value));
+            }
+        }
+
+        let common = last_key.iter().zip(&key).take_while(|(a, b)| a == b).count();
+
+        if common == last_key.len() {
+            // the previous key is a prefix of this one, so it becomes the
+            // value of a node that this key descends from
+            self.stack.push(Frame {
+                key: last_key,
+                value: Some(last_value),
+                children: Vec::new(),
+            });
+        } else {
+            let subtree = self.close(Subtree::Leaf(last_key, last_value), common);
+
+            match self.stack.last_mut() {
+                Some(frame) if frame.key.len() == common => attach(frame, subtree),
+                _ => {
+                    let mut frame = Frame {
+                        key: key[..common].to_vec(),
+                        value: None,
+                        children: Vec::new(),
+                    };
+                    attach(&mut frame, subtree);
+                    self.stack.push(frame);
+                }
+            }
+        }
+
+        self.last = Some((key, value));
+        self.len += 1;
+        Ok(())
+    }
+
+    fn finish(mut self) -> (ARTLink<V>, usize) {
+        let root = self.last.take().map(|(key, value)| {
+            let mut subtree = Subtree::Leaf(key, value);
+            while let Some(mut frame) = self.stack.pop() {
+                attach(&mut frame, subtree);
+                subtree = Subtree::Inner(frame);
+            }
+            subtree.into_node(0)
+        });
+
+        (root, self.len)
+    }
+
+    /// Attaches `subtree` to the open nodes deeper than `depth` bytes, closing
+    /// each of them, and returns the outermost subtree that was closed.
+    fn close(&mut self, mut subtree: Subtree<V>, depth: usize) -> Subtree<V> {
+        while self.stack.last().is_some_and(|frame| frame.key.len() > depth) {
+            let mut frame = self.stack.pop().unwrap();
+            attach(&mut frame, subtree);
+            subtree = Subtree::Inner(frame);
+        }
+
+        subtree
+    }
+}
+
+fn attach<V>(frame: &mut Frame<V>, subtree: Subtree<V>) {
+    let depth = frame.key.len();
+    let key_byte = subtree.full_key()[depth];
+    frame.children.push((key_byte, subtree.into_node(depth + 1)));
+}
+
+impl<K: ARTKey, V> ARTree<K, V> {
+    /// Builds a tree from entries in strictly ascending key order.
+    ///
+    /// Every inner node is created once, at its final size and with its final
+    /// compressed prefix, instead of growing and splitting as in repeated
+    /// inserts.
+    pub fn from_sorted_iter<I>(iter: I) -> Result<Self, BulkLoadError>
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let mut tree = ARTree::new();
+        tree.bulk_load(iter)?;
+        Ok(tree)
+    }
+
+    /// Merges entries in strictly ascending key order into the tree, taking
+    /// the new value for keys that are already present.
+    ///
+    /// The whole tree is rebuilt bottom-up in one pass, so this pays off when
+    /// the input is large compared to the tree. If the input turns out to be
+    /// unsorted, the tree keeps its old entries and the ones accepted before
+    /// the offending entry.
+    pub fn bulk_load<I>(&mut self, iter: I) -> Result<(), BulkLoadError>
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let mut existing = RawIter::new(self.root.take());
+        let mut next_existing = existing.next();
+        let mut builder = SortedBuilder::new();
+        let mut result = Ok(());
+
+        for (index, (key, value)) in iter.into_iter().enumerate() {
+            let bytes = key.convert_to_bytes().as_ref().to_vec();
+
+            while let Some((existing_key, _)) = &next_existing {
+                if *existing_key > bytes {
+                    break;
+                }
+                let (existing_key, existing_value) = next_existing.take().unwrap();
+                if existing_key < bytes {
+                    push_sorted(&mut builder, existing_key, existing_value);
+                }
+                next_existing = existing.next();
+            }
+
+            if let Err((order, _)) = builder.push(bytes, value) {
+                result = Err(match order {
+                    Ordering::Equal => BulkLoadError::Duplicate { index },
+                    _ => BulkLoadError::Unsorted { index },
+                });
+                break;
+            }
+        }
+
+        while let Some((key, value)) = next_existing {
+            push_sorted(&mut builder, key, value);
+            next_existing = existing.next();
+        }
+
+        *self = ARTree::from_builder(builder);
+        result
+    }
+
+    pub(crate) fn from_builder(builder: SortedBuilder<V>) -> Self {
+        let (root, len) = builder.finish();
+        ARTree {
+            root,
+            len,
+            _marker: Default::default(),
+        }
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<K: ARTKey, V: Send> ARTree<K, V> {
+    /// Builds a tree from entries in strictly ascending key order, like
+    /// `from_sorted_iter`, but builds the root's subtrees on the rayon pool.
+    ///
+    /// The input is split on the first byte in which its keys differ, which
+    /// is the first key byte unless all keys share a common prefix. All
+    /// entries are buffered before the parallel build starts.
+    pub fn par_from_sorted<I>(iter: I) -> Result<Self, BulkLoadError>
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        use rayon::prelude::*;
+
+        let mut entries: Vec<(ByteKey, V)> = Vec::new();
+
+        for (index, (key, value)) in iter.into_iter().enumerate() {
+            let bytes = key.convert_to_bytes().as_ref().to_vec();
+
+            if let Some((last_key, _)) = entries.last() {
+                match bytes.cmp(last_key) {
+                    Ordering::Greater => {}
+                    Ordering::Equal => return Err(BulkLoadError::Duplicate { index }),
+                    Ordering::Less => return Err(BulkLoadError::Unsorted { index }),
+                }
+            }
+            entries.push((bytes, value));
+        }
+
+        if entries.len() < 2 {
+            let mut builder = SortedBuilder::new();
+            for (key, value) in entries {
+                push_sorted(&mut builder, key, value);
+            }
+            return Ok(ARTree::from_builder(builder));
+        }
+
+        // sorted keys all share the prefix of the first and the last key,
+        // and only the first key can end there
+        let (first, last) = (&entries[0].0, &entries[entries.len() - 1].0);
+        let depth = first.iter().zip(last).take_while(|(a, b)| a == b).count();
+        let pkey = first[..depth].to_vec();
+
+        let mut entries = entries.into_iter().peekable();
+        let value = entries.next_if(|(key, _)| key.len() == depth).map(|(_, value)| value);
+
+        let mut groups: Vec<(u8, Vec<(ByteKey, V)>)> = Vec::new();
+        for (key, value) in entries {
+            match groups.last_mut() {
+                Some((key_byte, group)) if *key_byte == key[depth] => group.push((key, value)),
+                _ => groups.push((key[depth], vec![(key, value)])),
+            }
+        }
+
+        let subtrees: Vec<_> = groups.into_par_iter().map(|(key_byte, group)| {
+            let mut builder = SortedBuilder::new();
+            for (key, value) in group {
+                push_sorted(&mut builder, key, value);
+            }
+
+            let (subtree, len) = builder.finish();
+            let mut subtree = subtree.expect("groups are never empty");
+            subtree.pkey_mut().drain(..depth + 1);
+            (key_byte, subtree, len)
+        }).collect();
+
+        let mut inner = ARTInnerNode::with_capacity(subtrees.len());
+        let mut len = value.is_some() as usize;
+        for (key_byte, subtree, subtree_len) in subtrees {
+            inner.add_node(subtree, key_byte);
+            len += subtree_len;
+        }
+
+        Ok(ARTree {
+            root: Some(ARTNode::Inner(inner, pkey, value)),
+            len,
+            _marker: Default::default(),
+        })
+    }
+}
+
+fn push_sorted<V>(builder: &mut SortedBuilder<V>, key: ByteKey, value: V) {
+    if builder.push(key, value).is_err() {
+        unreachable!("tree entries are visited in ascending key order");
+    }
+}
@@ -0,0 +1,474 @@
+//! A tree that many threads can read and write at once.
+//!
+//! Synchronization follows the optimistic lock coupling scheme from "The ART
+//! of Practical Synchronization" (Leis et al.). Every inner node carries a
+//! version word that doubles as a write lock. Readers never write shared
+//! memory: they remember the version of each node they pass, and start over
+//! if a version changed before they were done with the node. Writers take
+//! the lock of the node they change, plus its parent's when the node itself
+//! is replaced by a grown, shrunk, split or merged copy.
+//!
+//! Replaced nodes and leaves are freed through crossbeam's epoch-based
+//! reclamation once no thread can still be reading them.
+//!
+//! `RowexARTree` shares the writers but drops the readers' validation, for
+//! readers that must never retry.
+
+mod node;
+mod rowex;
+mod sync;
+
+pub use rowex::RowexARTree;
+
+use crate::keys::ARTKey;
+use node::{alloc, free_subtree, new_leaf, Inner, Node, Restart};
+use sync::{AtomicUsize, Guard};
+
+use std::borrow::Borrow;
+use std::marker::PhantomData;
+use std::sync::atomic::Ordering::{AcqRel, Acquire, Relaxed, Release};
+
+pub struct ConcurrentARTree<K: ARTKey, V> {
+    /// A Node256 with an empty prefix that is never replaced, so every other
+    /// node has a parent to lock.
+    root: *mut Node<V>,
+    len: AtomicUsize,
+    _marker: PhantomData<fn() -> K>,
+}
+
+// SAFETY: the tree owns its values, hands out clones of them, and drops
+// replaced ones on whichever thread reclaims them.
+unsafe impl<K: ARTKey, V: Send + Sync> Send for ConcurrentARTree<K, V> {}
+unsafe impl<K: ARTKey, V: Send + Sync> Sync for ConcurrentARTree<K, V> {}
+
+/// What a delete removes from the node where its descent ended.
+#[derive(Clone, Copy, PartialEq)]
+enum Target {
+    Value,
+    Child(u8),
+}
+
+impl<K: ARTKey, V> Default for ConcurrentARTree<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: ARTKey, V> ConcurrentARTree<K, V> {
+    pub fn new() -> Self {
+        ConcurrentARTree {
+            root: alloc(Node::Inner(Inner::new(&[], 256))),
+            len: AtomicUsize::new(0),
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len.load(Relaxed)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn root(&self) -> &Inner<V> {
+        // SAFETY: the root lives as long as the tree.
+        match unsafe { &*self.root } {
+            Node::Inner(inner) => inner,
+            Node::Leaf(_) => unreachable!("the root is always an inner node"),
+        }
+    }
+}
+
+impl<K: ARTKey, V: Clone + Send + 'static> ConcurrentARTree<K, V> {
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ARTKey + ?Sized,
+    {
+        let bytes = key.convert_to_bytes();
+        let guard = sync::pin();
+        retry(|| self.try_get(bytes.as_ref(), &guard))
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ARTKey + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    pub fn insert(&self, key: K, value: V) -> Option<V> {
+        let bytes = key.convert_to_bytes();
+        let leaf = new_leaf(bytes.as_ref(), value);
+        let guard = sync::pin();
+        retry(|| self.try_insert(bytes.as_ref(), leaf, &guard))
+    }
+
+    pub fn delete<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ARTKey + ?Sized,
+    {
+        let bytes = key.convert_to_bytes();
+        let guard = sync::pin();
+        retry(|| self.try_delete(bytes.as_ref(), &guard))
+    }
+
+    fn try_get(&self, key: &[u8], guard: &Guard) -> Result<Option<V>, Restart> {
+        let mut node = self.root();
+        let mut version = node.read_lock()?;
+        let mut depth = 0;
+
+        loop {
+            if !key[depth..].starts_with(&node.prefix) {
+                node.check(version)?;
+                return Ok(None);
+            }
+
+            depth += node.prefix.len();
+            if depth == key.len() {
+                let value = deref(node.value.load(Acquire), guard).map(leaf_value).cloned();
+                node.check(version)?;
+                return Ok(value);
+            }
+
+            let child = node.find_child(key[depth]);
+            node.check(version)?;
+
+            match deref(child, guard) {
+                None => return Ok(None),
+                Some(Node::Leaf(leaf)) => {
+                    return Ok((*leaf.key == *key).then(|| leaf.value.clone()));
+                }
+                Some(Node::Inner(inner)) => {
+                    let child_version = inner.read_lock()?;
+                    node.check(version)?;
+
+                    node = inner;
+                    version = child_version;
+                    depth += 1;
+                }
+            }
+        }
+    }
+
+    fn try_insert(&self, key: &[u8], leaf: *mut Node<V>, guard: &Guard)
+        -> Result<Option<V>, Restart>
+    {
+        let mut parent: Option<(&Inner<V>, u64, u8)> = None;
+        let mut node_ptr = self.root;
+        let mut node = self.root();
+        let mut version = node.read_lock()?;
+        let mut depth = 0;
+
+        loop {
+            let matched = common_prefix(&node.prefix, &key[depth..]);
+
+            if matched < node.prefix.len() {
+                // the key either diverges from the prefix or ends inside it
+                let (parent, parent_version, parent_byte) =
+                    parent.expect("the root has an empty prefix");
+                lock_pair(parent, parent_version, node, version)?;
+
+                let split = Inner::new(&node.prefix[..matched], 2);
+                let rest = node.copy_with(&node.prefix[matched + 1..], node.live());
+                split.add_child(node.prefix[matched], alloc(Node::Inner(rest)));
+
+                if depth + matched == key.len() {
+                    split.value.store(leaf, Relaxed);
+                } else {
+                    split.add_child(key[depth + matched], leaf);
+                }
+
+                self.replace(parent, parent_byte, node, alloc(Node::Inner(split)), node_ptr, guard);
+                return Ok(self.replaced(std::ptr::null_mut(), guard));
+            }
+
+            depth += node.prefix.len();
+            if depth == key.len() {
+                node.upgrade(version)?;
+                let old = node.value.swap(leaf, AcqRel);
+                node.unlock();
+                return Ok(self.replaced(old, guard));
+            }
+
+            let key_byte = key[depth];
+            let child = node.find_child(key_byte);
+            node.check(version)?;
+
+            match deref(child, guard) {
+                None if node.has_room(key_byte) => {
+                    node.upgrade(version)?;
+                    node.add_child(key_byte, leaf);
+                    node.unlock();
+                    return Ok(self.replaced(child, guard));
+                }
+                None => {
+                    let (parent, parent_version, parent_byte) =
+                        parent.expect("the root never fills up");
+                    lock_pair(parent, parent_version, node, version)?;
+
+                    let grown = node.copy_with(&node.prefix, node.live() + 1);
+                    grown.add_child(key_byte, leaf);
+
+                    self.replace(parent, parent_byte, node, alloc(Node::Inner(grown)), node_ptr, guard);
+                    return Ok(self.replaced(child, guard));
+                }
+                Some(Node::Leaf(existing)) => {
+                    node.upgrade(version)?;
+                    let slot = node.slot(key_byte).expect("a child has a slot");
+
+                    if *existing.key == *key {
+                        slot.store(leaf, Release);
+                        node.unlock();
+                        return Ok(self.replaced(child, guard));
+                    }
+
+                    // both keys go below a new node holding their common part
+                    let existing_rest = &existing.key[depth + 1..];
+                    let rest = &key[depth + 1..];
+                    let common = common_prefix(existing_rest, rest);
+                    let inner = Inner::new(&rest[..common], 2);
+
+                    for (rest, leaf) in [(existing_rest, child), (rest, leaf)] {
+                        if rest.len() == common {
+                            inner.value.store(leaf, Relaxed);
+                        } else {
+                            inner.add_child(rest[common], leaf);
+                        }
+                    }
+
+                    slot.store(alloc(Node::Inner(inner)), Release);
+                    node.unlock();
+                    return Ok(self.replaced(std::ptr::null_mut(), guard));
+                }
+                Some(Node::Inner(inner)) => {
+                    let child_version = inner.read_lock()?;
+                    node.check(version)?;
+
+                    parent = Some((node, version, key_byte));
+                    node_ptr = child;
+                    node = inner;
+                    version = child_version;
+                    depth += 1;
+                }
+            }
+        }
+    }
+
+    fn try_delete(&self, key: &[u8], guard: &Guard) -> Result<Option<V>, Restart> {
+        let mut parent: Option<(&Inner<V>, u64, u8)> = None;
+        let mut node_ptr = self.root;
+        let mut node = self.root();
+        let mut version = node.read_lock()?;
+        let mut depth = 0;
+
+        let target = loop {
+            if !key[depth..].starts_with(&node.prefix) {
+                node.check(version)?;
+                return Ok(None);
+            }
+
+            depth += node.prefix.len();
+            if depth == key.len() {
+                let found = !node.value.load(Acquire).is_null();
+                node.check(version)?;
+
+                if !found {
+                    return Ok(None);
+                }
+                break Target::Value;
+            }
+
+            let key_byte = key[depth];
+            let child = node.find_child(key_byte);
+            node.check(version)?;
+
+            match deref(child, guard) {
+                None => return Ok(None),
+                Some(Node::Leaf(leaf)) if *leaf.key == *key => break Target::Child(key_byte),
+                Some(Node::Leaf(_)) => return Ok(None),
+                Some(Node::Inner(inner)) => {
+                    let child_version = inner.read_lock()?;
+                    node.check(version)?;
+
+                    parent = Some((node, version, key_byte));
+                    node_ptr = child;
+                    node = inner;
+                    version = child_version;
+                    depth += 1;
+                }
+            }
+        };
+
+        // what the node holds once the target is gone; these reads are
+        // validated by the lock upgrade below
+        let live = node.live() - matches!(target, Target::Child(_)) as usize;
+        let has_value = target != Target::Value && !node.value.load(Acquire).is_null();
+        let things = live + has_value as usize;
+
+        let (parent, parent_version, parent_byte) = match parent {
+            Some(parent) if things < 2 || node.is_shrinkable(live) => parent,
+            _ => {
+                node.upgrade(version)?;
+                let removed = remove(node, target);
+                node.unlock();
+                return Ok(self.removed(removed, guard));
+            }
+        };
+
+        lock_pair(parent, parent_version, node, version)?;
+
+        let replacement = if things >= 2 {
+            let removed = remove(node, target);
+            (alloc(Node::Inner(node.copy_with(&node.prefix, live))), removed)
+        } else if has_value {
+            // only the value is left, and it already is a leaf with the
+            // node's full key
+            let removed = remove(node, target);
+            (node.value.load(Acquire), removed)
+        } else {
+            let (key_byte, child) = node.children().into_iter()
+                .find(|&(key_byte, _)| target != Target::Child(key_byte))
+                .expect("an inner node keeps at least two entries");
+
+            match deref(child, guard) {
+                Some(Node::Inner(inner)) => {
+                    // the only child takes over the node's prefix
+                    let locked = inner.read_lock().and_then(|v| inner.upgrade(v));
+                    if locked.is_err() {
+                        node.unlock();
+                        parent.unlock();
+                        return Err(Restart);
+                    }
+
+                    let mut prefix = node.prefix.to_vec();
+                    prefix.push(key_byte);
+                    prefix.extend_from_slice(&inner.prefix);
+                    let merged = inner.copy_with(&prefix, inner.live());
+
+                    inner.unlock_obsolete();
+                    // SAFETY: the child is unlinked together with the node.
+                    unsafe { retire(guard, child) };
+
+                    let removed = remove(node, target);
+                    (alloc(Node::Inner(merged)), removed)
+                }
+                _ => (child, remove(node, target)),
+            }
+        };
+
+        let (replacement, removed) = replacement;
+        self.replace(parent, parent_byte, node, replacement, node_ptr, guard);
+        Ok(self.removed(removed, guard))
+    }
+
+    /// Links `replacement` in place of the locked `node` and retires the
+    /// node. Both locks are released.
+    fn replace(&self,
+               parent: &Inner<V>,
+               parent_byte: u8,
+               node: &Inner<V>,
+               replacement: *mut Node<V>,
+               node_ptr: *mut Node<V>,
+               guard: &Guard) {
+        parent.slot(parent_byte).expect("a child has a slot").store(replacement, Release);
+        node.unlock_obsolete();
+        parent.unlock();
+
+        // SAFETY: the node is unreachable for anyone pinned from now on.
+        unsafe { retire(guard, node_ptr) };
+    }
+
+    /// Accounts for a leaf that an insert unlinked, returning its value.
+    fn replaced(&self, old: *mut Node<V>, guard: &Guard) -> Option<V> {
+        match deref(old, guard) {
+            None => {
+                self.len.fetch_add(1, Relaxed);
+                None
+            }
+            Some(old_leaf) => {
+                let value = leaf_value(old_leaf).clone();
+                // SAFETY: the leaf was unlinked by the caller.
+                unsafe { retire(guard, old) };
+                Some(value)
+            }
+        }
+    }
+
+    /// Accounts for a leaf that a delete unlinked, returning its value.
+    fn removed(&self, leaf: *mut Node<V>, guard: &Guard) -> Option<V> {
+        self.len.fetch_sub(1, Relaxed);
+        let value = leaf_value(deref(leaf, guard).expect("the target was found")).clone();
+
+        // SAFETY: the leaf was unlinked by the caller.
+        unsafe { retire(guard, leaf) };
+        Some(value)
+    }
+}
+
+impl<K: ARTKey, V> Drop for ConcurrentARTree<K, V> {
+    fn drop(&mut self) {
+        // SAFETY: `&mut self` rules out other threads, and retired nodes are
+        // no longer linked into the tree.
+        unsafe { free_subtree(self.root) };
+    }
+}
+
+/// Runs an optimistic operation until it gets through without conflicts.
+fn retry<T>(mut operation: impl FnMut() -> Result<T, Restart>) -> T {
+    let mut attempts = 0u32;
+
+    loop {
+        match operation() {
+            Ok(result) => return result,
+            Err(Restart) => sync::pause(attempts),
+        }
+        attempts += 1;
+    }
+}
+
+/// Locks a parent and its child, releasing the parent if the child changed.
+fn lock_pair<V>(parent: &Inner<V>, parent_version: u64, node: &Inner<V>, version: u64)
+    -> Result<(), Restart>
+{
+    parent.upgrade(parent_version)?;
+    node.upgrade(version).inspect_err(|_| parent.unlock())
+}
+
+/// Unlinks the target from the locked node.
+fn remove<V>(node: &Inner<V>, target: Target) -> *mut Node<V> {
+    match target {
+        Target::Value => node.value.swap(std::ptr::null_mut(), AcqRel),
+        Target::Child(key_byte) => node.remove_child(key_byte),
+    }
+}
+
+fn deref<V>(node: *mut Node<V>, _guard: &Guard) -> Option<&Node<V>> {
+    // SAFETY: nodes are only freed through the epoch collector, which keeps
+    // everything that was linked while the guard is pinned alive.
+    unsafe { node.as_ref() }
+}
+
+fn leaf_value<V>(node: &Node<V>) -> &V {
+    match node {
+        Node::Leaf(leaf) => &leaf.value,
+        Node::Inner(_) => unreachable!("values are stored in leaves"),
+    }
+}
+
+/// Frees an unlinked node once every thread pinned before has moved on.
+///
+/// # Safety
+///
+/// `node` must be unlinked from the tree and retired only once. Its children
+/// are not freed, as they are either linked elsewhere or retired separately.
+unsafe fn retire<V: Send + 'static>(guard: &Guard, node: *mut Node<V>) {
+    guard.defer_unchecked(move || drop(Box::from_raw(node)));
+}
+
+fn common_prefix(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(a, b)| a == b).count()
+}
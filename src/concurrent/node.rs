@@ -0,0 +1,334 @@
+use std::array;
+use std::ptr;
+use std::sync::atomic::Ordering::{AcqRel, Acquire, Relaxed, Release};
+use super::sync::{AtomicPtr, AtomicU16, AtomicU64, AtomicU8};
+
+// The version word of an inner node. Writers set `LOCKED` while they change
+// the node and bump the counter above the two flag bits when they are done;
+// `OBSOLETE` marks a node that has been replaced and is waiting to be freed.
+const OBSOLETE: u64 = 0b01;
+const LOCKED: u64 = 0b10;
+
+const NODE48_EMPTY: u8 = u8::MAX;
+
+/// Returned when an optimistic step saw a concurrent change and the whole
+/// operation has to start over from the root.
+pub(crate) struct Restart;
+
+pub(crate) enum Node<V> {
+    Leaf(Leaf<V>),
+    Inner(Inner<V>),
+}
+
+/// Leaves keep their full key, so they never change when the inner nodes
+/// above them are split or merged.
+pub(crate) struct Leaf<V> {
+    pub(crate) key: Box<[u8]>,
+    pub(crate) value: V,
+}
+
+/// An inner node shared between threads.
+///
+/// The compressed prefix never changes once the node is published; a node
+/// that needs a different prefix, or a different node type, is replaced by a
+/// copy. Slots are never handed to a different key byte during the node's
+/// lifetime, so a reader that matched a key byte never reads the child of
+/// another one.
+pub(crate) struct Inner<V> {
+    version: AtomicU64,
+    pub(crate) prefix: Box<[u8]>,
+    /// A leaf holding the key that ends at this node, if any.
+    pub(crate) value: AtomicPtr<Node<V>>,
+    /// Number of non-null children, only used by writers holding the lock.
+    live: AtomicU16,
+    children: Children<V>,
+}
+
+enum Children<V> {
+    N4(Box<Sparse<V, 4>>),
+    N16(Box<Sparse<V, 16>>),
+    N48(Box<Indexed<V>>),
+    N256(Box<Direct<V>>),
+}
+
+/// Node4 and Node16: slots are appended, and a removed child leaves its key
+/// byte behind with a null child until the node is copied.
+struct Sparse<V, const N: usize> {
+    used: AtomicU8,
+    keys: [AtomicU8; N],
+    children: [AtomicPtr<Node<V>>; N],
+}
+
+/// Node48: slots are handed out in order and not reused until the node is
+/// copied.
+struct Indexed<V> {
+    used: AtomicU8,
+    index: [AtomicU8; 256],
+    children: [AtomicPtr<Node<V>>; 48],
+}
+
+struct Direct<V> {
+    children: [AtomicPtr<Node<V>>; 256],
+}
+
+pub(crate) fn alloc<V>(node: Node<V>) -> *mut Node<V> {
+    Box::into_raw(Box::new(node))
+}
+
+pub(crate) fn new_leaf<V>(key: &[u8], value: V) -> *mut Node<V> {
+    alloc(Node::Leaf(Leaf {
+        key: key.into(),
+        value,
+    }))
+}
+
+fn null_children<V, const N: usize>() -> [AtomicPtr<Node<V>>; N] {
+    array::from_fn(|_| AtomicPtr::new(ptr::null_mut()))
+}
+
+impl<V, const N: usize> Sparse<V, N> {
+    fn boxed() -> Box<Self> {
+        Box::new(Sparse {
+            used: AtomicU8::new(0),
+            keys: array::from_fn(|_| AtomicU8::new(0)),
+            children: null_children(),
+        })
+    }
+
+    fn slot(&self, key_byte: u8) -> Option<&AtomicPtr<Node<V>>> {
+        let used = self.used.load(Acquire) as usize;
+        let index = self.keys[..used.min(N)].iter().position(|key| key.load(Acquire) == key_byte)?;
+        Some(&self.children[index])
+    }
+
+    fn free_slot(&self, key_byte: u8) -> Option<&AtomicPtr<Node<V>>> {
+        self.slot(key_byte).or_else(|| {
+            let used = self.used.load(Relaxed) as usize;
+            if used == N {
+                return None;
+            }
+
+            // publish the key before the slot count that makes it visible
+            self.keys[used].store(key_byte, Release);
+            self.used.store(used as u8 + 1, Release);
+            Some(&self.children[used])
+        })
+    }
+
+    fn entries(&self) -> Vec<(u8, *mut Node<V>)> {
+        let used = self.used.load(Acquire) as usize;
+        let mut entries: Vec<_> = (0..used.min(N))
+            .map(|i| (self.keys[i].load(Acquire), self.children[i].load(Acquire)))
+            .filter(|(_, child)| !child.is_null())
+            .collect();
+        entries.sort_unstable_by_key(|&(key, _)| key);
+        entries
+    }
+}
+
+impl<V> Indexed<V> {
+    fn boxed() -> Box<Self> {
+        Box::new(Indexed {
+            used: AtomicU8::new(0),
+            index: array::from_fn(|_| AtomicU8::new(NODE48_EMPTY)),
+            children: null_children(),
+        })
+    }
+
+    fn slot(&self, key_byte: u8) -> Option<&AtomicPtr<Node<V>>> {
+        match self.index[key_byte as usize].load(Acquire) {
+            NODE48_EMPTY => None,
+            index => self.children.get(index as usize),
+        }
+    }
+
+    fn free_slot(&self, key_byte: u8) -> Option<&AtomicPtr<Node<V>>> {
+        self.slot(key_byte).or_else(|| {
+            let used = self.used.load(Relaxed);
+            if used as usize == self.children.len() {
+                return None;
+            }
+
+            self.used.store(used + 1, Relaxed);
+            self.index[key_byte as usize].store(used, Release);
+            Some(&self.children[used as usize])
+        })
+    }
+
+    fn entries(&self) -> Vec<(u8, *mut Node<V>)> {
+        (0..=u8::MAX)
+            .filter_map(|key_byte| {
+                let child = self.slot(key_byte)?.load(Acquire);
+                (!child.is_null()).then_some((key_byte, child))
+            })
+            .collect()
+    }
+}
+
+impl<V> Inner<V> {
+    /// Returns an unpublished node of the smallest type that holds
+    /// `capacity` children.
+    pub(crate) fn new(prefix: &[u8], capacity: usize) -> Self {
+        let children = match capacity {
+            0..=4 => Children::N4(Sparse::boxed()),
+            5..=16 => Children::N16(Sparse::boxed()),
+            17..=48 => Children::N48(Indexed::boxed()),
+            _ => Children::N256(Box::new(Direct { children: null_children() })),
+        };
+
+        Inner {
+            version: AtomicU64::new(0),
+            prefix: prefix.into(),
+            value: AtomicPtr::new(ptr::null_mut()),
+            live: AtomicU16::new(0),
+            children,
+        }
+    }
+
+    /// Copies the node under a new prefix into the smallest node type that
+    /// holds `capacity` children. The children are shared with the original.
+    pub(crate) fn copy_with(&self, prefix: &[u8], capacity: usize) -> Self {
+        let copy = Inner::new(prefix, capacity);
+        copy.value.store(self.value.load(Acquire), Relaxed);
+
+        for (key_byte, child) in self.children() {
+            copy.add_child(key_byte, child);
+        }
+        copy
+    }
+
+    pub(crate) fn read_lock(&self) -> Result<u64, Restart> {
+        let version = self.version.load(Acquire);
+        if version & (LOCKED | OBSOLETE) != 0 {
+            Err(Restart)
+        } else {
+            Ok(version)
+        }
+    }
+
+    /// Succeeds if nothing changed the node since `read_lock` returned
+    /// `version`.
+    pub(crate) fn check(&self, version: u64) -> Result<(), Restart> {
+        if self.version.load(Acquire) == version {
+            Ok(())
+        } else {
+            Err(Restart)
+        }
+    }
+
+    pub(crate) fn upgrade(&self, version: u64) -> Result<(), Restart> {
+        self.version
+            .compare_exchange(version, version + LOCKED, Acquire, Relaxed)
+            .map(|_| ())
+            .map_err(|_| Restart)
+    }
+
+    pub(crate) fn unlock(&self) {
+        // carries the lock bit into the counter
+        self.version.fetch_add(LOCKED, Release);
+    }
+
+    pub(crate) fn unlock_obsolete(&self) {
+        self.version.fetch_add(LOCKED | OBSOLETE, Release);
+    }
+
+    /// The slot currently assigned to `key_byte`, whether or not it holds a
+    /// child.
+    pub(crate) fn slot(&self, key_byte: u8) -> Option<&AtomicPtr<Node<V>>> {
+        match &self.children {
+            Children::N4(node) => node.slot(key_byte),
+            Children::N16(node) => node.slot(key_byte),
+            Children::N48(node) => node.slot(key_byte),
+            Children::N256(node) => Some(&node.children[key_byte as usize]),
+        }
+    }
+
+    /// Returns the child under `key_byte`, or null.
+    pub(crate) fn find_child(&self, key_byte: u8) -> *mut Node<V> {
+        self.slot(key_byte).map_or(ptr::null_mut(), |slot| slot.load(Acquire))
+    }
+
+    /// Whether `add_child` can take `key_byte` without replacing the node.
+    pub(crate) fn has_room(&self, key_byte: u8) -> bool {
+        let full = match &self.children {
+            Children::N4(node) => node.used.load(Relaxed) as usize == 4,
+            Children::N16(node) => node.used.load(Relaxed) as usize == 16,
+            Children::N48(node) => node.used.load(Relaxed) as usize == 48,
+            Children::N256(_) => false,
+        };
+        !full || self.slot(key_byte).is_some()
+    }
+
+    /// Stores a child under a key byte that has none. The caller holds the
+    /// lock, or owns the unpublished node, and checked `has_room`.
+    pub(crate) fn add_child(&self, key_byte: u8, child: *mut Node<V>) {
+        let slot = match &self.children {
+            Children::N4(node) => node.free_slot(key_byte),
+            Children::N16(node) => node.free_slot(key_byte),
+            Children::N48(node) => node.free_slot(key_byte),
+            Children::N256(node) => Some(&node.children[key_byte as usize]),
+        };
+
+        slot.expect("inner node has no room left").store(child, Release);
+        self.live.fetch_add(1, Relaxed);
+    }
+
+    /// Unlinks the child under `key_byte` and returns it. The caller holds
+    /// the lock.
+    pub(crate) fn remove_child(&self, key_byte: u8) -> *mut Node<V> {
+        let child = self.slot(key_byte).map_or(ptr::null_mut(), |slot| slot.swap(ptr::null_mut(), AcqRel));
+        if !child.is_null() {
+            self.live.fetch_sub(1, Relaxed);
+        }
+        child
+    }
+
+    pub(crate) fn live(&self) -> usize {
+        self.live.load(Relaxed) as usize
+    }
+
+    /// Whether a node with `live` children should be copied into a smaller
+    /// node type. The margin keeps a node from flipping between two types.
+    pub(crate) fn is_shrinkable(&self, live: usize) -> bool {
+        match &self.children {
+            Children::N4(_) => false,
+            Children::N16(_) => live <= 3,
+            Children::N48(_) => live <= 12,
+            Children::N256(_) => live <= 40,
+        }
+    }
+
+    /// The children in key byte order.
+    pub(crate) fn children(&self) -> Vec<(u8, *mut Node<V>)> {
+        match &self.children {
+            Children::N4(node) => node.entries(),
+            Children::N16(node) => node.entries(),
+            Children::N48(node) => node.entries(),
+            Children::N256(node) => (0..=u8::MAX)
+                .filter_map(|key_byte| {
+                    let child = node.children[key_byte as usize].load(Acquire);
+                    (!child.is_null()).then_some((key_byte, child))
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Frees a subtree that no other thread can reach anymore.
+///
+/// # Safety
+///
+/// `node` must come from `alloc`, and nothing may use it or its descendants
+/// afterwards.
+pub(crate) unsafe fn free_subtree<V>(node: *mut Node<V>) {
+    let node = Box::from_raw(node);
+    if let Node::Inner(inner) = &*node {
+        let value = inner.value.load(Relaxed);
+        if !value.is_null() {
+            free_subtree(value);
+        }
+        for (_, child) in inner.children() {
+            free_subtree(child);
+        }
+    }
+}
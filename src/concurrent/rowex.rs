@@ -0,0 +1,103 @@
+use super::node::Node;
+use super::{deref, leaf_value, sync, ConcurrentARTree};
+use crate::keys::ARTKey;
+
+use std::borrow::Borrow;
+use std::sync::atomic::Ordering::Acquire;
+
+/// A concurrent tree whose readers never lock, validate or retry.
+///
+/// This is the ROWEX (read-optimized write exclusion) mode of the
+/// synchronization paper. Writers are the ones of `ConcurrentARTree` and
+/// already keep every node readable at all times:
+///
+/// * a node is never changed to another type or given another compressed
+///   prefix in place. Growing, shrinking, splitting and merging build a new
+///   node and swap the parent's child pointer to it,
+/// * new children and subtrees are fully built before their pointer is
+///   stored, and node slots publish their key byte before their child,
+/// * a slot keeps its key byte for the node's whole lifetime.
+///
+/// A reader that ends up in a node which was just replaced keeps reading
+/// the old node, whose children stay alive until its epoch is over. It may
+/// miss writes that completed while it was there, but it never sees a torn
+/// node and never waits on a writer.
+pub struct RowexARTree<K: ARTKey, V> {
+    tree: ConcurrentARTree<K, V>,
+}
+
+impl<K: ARTKey, V> Default for RowexARTree<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: ARTKey, V> RowexARTree<K, V> {
+    pub fn new() -> Self {
+        RowexARTree {
+            tree: ConcurrentARTree::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+}
+
+impl<K: ARTKey, V: Clone + Send + 'static> RowexARTree<K, V> {
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ARTKey + ?Sized,
+    {
+        let bytes = key.convert_to_bytes();
+        let key = bytes.as_ref();
+        let guard = sync::pin();
+
+        let mut node = self.tree.root();
+        let mut depth = 0;
+
+        loop {
+            if !key[depth..].starts_with(&node.prefix) {
+                return None;
+            }
+
+            depth += node.prefix.len();
+            if depth == key.len() {
+                return deref(node.value.load(Acquire), &guard).map(leaf_value).cloned();
+            }
+
+            match deref(node.find_child(key[depth]), &guard)? {
+                Node::Leaf(leaf) => return (*leaf.key == *key).then(|| leaf.value.clone()),
+                Node::Inner(inner) => {
+                    node = inner;
+                    depth += 1;
+                }
+            }
+        }
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ARTKey + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    pub fn insert(&self, key: K, value: V) -> Option<V> {
+        self.tree.insert(key, value)
+    }
+
+    pub fn delete<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ARTKey + ?Sized,
+    {
+        self.tree.delete(key)
+    }
+}
@@ -0,0 +1,43 @@
+//! The atomics and reclamation used by the concurrent trees, swapped for
+//! loom's models when the crate is built with `--cfg loom`.
+
+#[cfg(not(loom))]
+pub(crate) use std::sync::atomic::{AtomicPtr, AtomicU16, AtomicU64, AtomicU8, AtomicUsize};
+
+#[cfg(loom)]
+pub(crate) use loom::sync::atomic::{AtomicPtr, AtomicU16, AtomicU64, AtomicU8, AtomicUsize};
+
+#[cfg(not(loom))]
+pub(crate) use crossbeam_epoch::{pin, Guard};
+
+/// Loom cannot see through crossbeam's epochs, so under loom retired nodes
+/// are leaked instead of freed.
+#[cfg(loom)]
+pub(crate) struct Guard;
+
+#[cfg(loom)]
+pub(crate) fn pin() -> Guard {
+    Guard
+}
+
+#[cfg(loom)]
+impl Guard {
+    pub(crate) unsafe fn defer_unchecked<F: FnOnce()>(&self, _f: F) {}
+}
+
+/// Waits before an operation that saw a locked or changed node retries.
+pub(crate) fn pause(attempts: u32) {
+    #[cfg(loom)]
+    {
+        let _ = attempts;
+        loom::thread::yield_now();
+    }
+
+    #[cfg(not(loom))]
+    if attempts < 64 {
+        std::hint::spin_loop();
+    } else {
+        // the lock holder may have been preempted
+        std::thread::yield_now();
+    }
+}
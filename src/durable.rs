@@ -0,0 +1,374 @@
+use crate::build::SortedBuilder;
+use crate::frozen::FrozenARTree;
+use crate::keys::ARTKey;
+use crate::snapshot::{FrozenValue, SnapshotError};
+use crate::ARTree;
+
+use std::borrow::Borrow;
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+// The log starts with a 16 byte header (magic, version, reserved) followed by
+// records. A record is its `u32` payload length, the CRC-32 of the payload
+// and the payload itself: an op byte, the `u32` length of the encoded key,
+// the key and, for inserts, the `FrozenValue` bytes of the value. All
+// integers are little-endian.
+//
+// Replay stops at the first record that is cut short or fails its checksum,
+// which is where a crash interrupted the last write, and drops the rest.
+const LOG_MAGIC: [u8; 8] = *b"RARTWAL\0";
+const LOG_VERSION: u32 = 1;
+const LOG_HEADER_LEN: u64 = 16;
+const RECORD_HEADER_LEN: usize = 8;
+
+const OP_INSERT: u8 = 1;
+const OP_DELETE: u8 = 2;
+
+const SNAPSHOT_FILE: &str = "snapshot";
+const SNAPSHOT_TMP_FILE: &str = "snapshot.tmp";
+const LOG_FILE: &str = "wal";
+
+/// When `DurableARTree` forces its log to disk. A write is durable once the
+/// log has been synced after it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncPolicy {
+    /// Syncs before every `insert` and `delete` returns.
+    Always,
+    /// Syncs once this many writes are pending, and on `sync`.
+    Batch(usize),
+    /// Syncs only on `sync`, `checkpoint` and drop.
+    Manual,
+}
+
+#[derive(Debug)]
+pub enum DurableError {
+    Io(io::Error),
+    Snapshot(SnapshotError),
+    /// The log file does not start with the log magic bytes.
+    NotALog,
+    UnsupportedLogVersion(u32),
+}
+
+impl fmt::Display for DurableError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DurableError::Io(err) => write!(f, "log I/O error: {}", err),
+            DurableError::Snapshot(err) => write!(f, "cannot load snapshot: {}", err),
+            DurableError::NotALog => f.write_str("not an ARTree log"),
+            DurableError::UnsupportedLogVersion(version) => {
+                write!(f, "unsupported log version {}", version)
+            }
+        }
+    }
+}
+
+impl Error for DurableError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DurableError::Io(err) => Some(err),
+            DurableError::Snapshot(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for DurableError {
+    fn from(err: io::Error) -> Self {
+        DurableError::Io(err)
+    }
+}
+
+impl From<SnapshotError> for DurableError {
+    fn from(err: SnapshotError) -> Self {
+        DurableError::Snapshot(err)
+    }
+}
+
+/// An `ARTree` that survives restarts.
+///
+/// The tree lives in a directory holding a snapshot and a write-ahead log.
+/// Every `insert` and `delete` is appended to the log before it changes the
+/// tree in memory, and `open` rebuilds the tree from the snapshot and the
+/// log written after it. `checkpoint` writes a new snapshot and empties the
+/// log.
+///
+/// If writing the log fails, the log may end in a partial record, so every
+/// later write fails too until the tree is opened again.
+pub struct DurableARTree<K: ARTKey, V: FrozenValue> {
+    tree: ARTree<K, V>,
+    dir: PathBuf,
+    log: BufWriter<File>,
+    policy: SyncPolicy,
+    /// Writes appended since the last sync.
+    pending: usize,
+    failed: bool,
+}
+
+impl<K: ARTKey, V> DurableARTree<K, V>
+where
+    V: FrozenValue + for<'a> From<V::Ref<'a>>,
+{
+    /// Opens the tree stored in `dir`, creating an empty one if there is
+    /// none.
+    pub fn open<P: AsRef<Path>>(dir: P, policy: SyncPolicy) -> Result<Self, DurableError> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+
+        let mut tree = match fs::read(dir.join(SNAPSHOT_FILE)) {
+            Ok(image) => load_snapshot(image)?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => ARTree::new(),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false)
+                                         .open(dir.join(LOG_FILE))?;
+        let mut log = Vec::new();
+        file.read_to_end(&mut log)?;
+
+        // a header cut short by a crash or a full disk holds no records, so
+        // it is as good as a new log
+        let header = log_header();
+        if log.len() < header.len() && header.starts_with(&log) {
+            file.set_len(0)?;
+            file.seek(SeekFrom::Start(0))?;
+            file.write_all(&header)?;
+            file.sync_all()?;
+            sync_dir(&dir)?;
+        } else {
+            let end = replay(&log, &mut tree)?;
+            if end < log.len() {
+                file.set_len(end as u64)?;
+                file.sync_all()?;
+            }
+        }
+        file.seek(SeekFrom::End(0))?;
+
+        Ok(DurableARTree {
+            tree,
+            dir,
+            log: BufWriter::new(file),
+            policy,
+            pending: 0,
+            failed: false,
+        })
+    }
+}
+
+impl<K: ARTKey, V: FrozenValue> DurableARTree<K, V> {
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: ARTKey + ?Sized,
+    {
+        self.tree.get(key)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ARTKey + ?Sized,
+    {
+        self.tree.contains_key(key)
+    }
+
+    /// The tree in memory, for iteration and range queries.
+    pub fn tree(&self) -> &ARTree<K, V> {
+        &self.tree
+    }
+
+    /// Logs and applies an insert. The write is durable when this returns
+    /// under `SyncPolicy::Always`, and after the next sync otherwise.
+    pub fn insert(&mut self, key: K, value: V) -> io::Result<Option<V>> {
+        self.append(OP_INSERT, key.convert_to_bytes().as_ref(), Some(&value))?;
+        Ok(self.tree.insert(key, value))
+    }
+
+    /// Logs and applies a delete. Deleting a missing key writes nothing.
+    pub fn delete<Q>(&mut self, key: &Q) -> io::Result<Option<V>>
+    where
+        K: Borrow<Q>,
+        Q: ARTKey + ?Sized,
+    {
+        if !self.tree.contains_key(key) {
+            return Ok(None);
+        }
+
+        self.append(OP_DELETE, key.convert_to_bytes().as_ref(), None)?;
+        Ok(self.tree.delete(key))
+    }
+
+    /// Forces every logged write to disk.
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.check_failed()?;
+
+        let result = self.log.flush().and_then(|_| self.log.get_ref().sync_data());
+        self.failed = result.is_err();
+        self.pending = 0;
+        result
+    }
+
+    /// Writes a snapshot of the tree and empties the log.
+    ///
+    /// The snapshot replaces the old one atomically, so a crash at any point
+    /// leaves either the old snapshot and the full log, or the new snapshot
+    /// and a log that replays onto it without changing it.
+    pub fn checkpoint(&mut self) -> io::Result<()> {
+        self.sync()?;
+
+        let tmp = self.dir.join(SNAPSHOT_TMP_FILE);
+        let file = File::create(&tmp)?;
+        self.tree.write_snapshot(BufWriter::new(&file))?;
+        file.sync_all()?;
+        fs::rename(&tmp, self.dir.join(SNAPSHOT_FILE))?;
+        sync_dir(&self.dir)?;
+
+        let log = self.log.get_mut();
+        log.set_len(LOG_HEADER_LEN)?;
+        log.seek(SeekFrom::End(0))?;
+        log.sync_all()
+    }
+
+    fn append(&mut self, op: u8, key: &[u8], value: Option<&V>) -> io::Result<()> {
+        self.check_failed()?;
+
+        let mut record = vec![0; RECORD_HEADER_LEN];
+        record.push(op);
+        record.extend_from_slice(&(key.len() as u32).to_le_bytes());
+        record.extend_from_slice(key);
+        if let Some(value) = value {
+            value.freeze(&mut record);
+        }
+
+        let payload_len = (record.len() - RECORD_HEADER_LEN) as u32;
+        let checksum = crc32fast::hash(&record[RECORD_HEADER_LEN..]);
+        record[..4].copy_from_slice(&payload_len.to_le_bytes());
+        record[4..8].copy_from_slice(&checksum.to_le_bytes());
+
+        if let Err(err) = self.log.write_all(&record) {
+            self.failed = true;
+            return Err(err);
+        }
+        self.pending += 1;
+
+        match self.policy {
+            SyncPolicy::Always => self.sync(),
+            SyncPolicy::Batch(writes) if self.pending >= writes => self.sync(),
+            _ => Ok(()),
+        }
+    }
+
+    fn check_failed(&self) -> io::Result<()> {
+        if self.failed {
+            Err(io::Error::other("an earlier log write failed, reopen the tree"))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl<K: ARTKey, V: FrozenValue> Drop for DurableARTree<K, V> {
+    fn drop(&mut self) {
+        if !self.failed {
+            let _ = self.sync();
+        }
+    }
+}
+
+fn load_snapshot<K: ARTKey, V>(image: Vec<u8>) -> Result<ARTree<K, V>, SnapshotError>
+where
+    V: FrozenValue + for<'a> From<V::Ref<'a>>,
+{
+    let frozen = FrozenARTree::<K, V, _>::from_bytes(image)?;
+    let mut raw = frozen.raw_iter();
+    let mut builder = SortedBuilder::new();
+
+    while let Some((key, value)) = raw.next() {
+        let pushed = builder.push(key, V::from(value));
+        // a snapshot that passed its checksum was written in key order
+        assert!(pushed.is_ok(), "snapshot keys out of order");
+    }
+    Ok(ARTree::from_builder(builder))
+}
+
+fn log_header() -> [u8; LOG_HEADER_LEN as usize] {
+    let mut header = [0; LOG_HEADER_LEN as usize];
+    header[..LOG_MAGIC.len()].copy_from_slice(&LOG_MAGIC);
+    header[LOG_MAGIC.len()..LOG_MAGIC.len() + 4].copy_from_slice(&LOG_VERSION.to_le_bytes());
+    header
+}
+
+/// Applies the records of `log` to `tree` and returns where the intact part
+/// of the log ends.
+fn replay<K: ARTKey, V>(log: &[u8], tree: &mut ARTree<K, V>) -> Result<usize, DurableError>
+where
+    V: FrozenValue + for<'a> From<V::Ref<'a>>,
+{
+    if log.len() < LOG_MAGIC.len() || log[..LOG_MAGIC.len()] != LOG_MAGIC {
+        return Err(DurableError::NotALog);
+    }
+    if log.len() < LOG_HEADER_LEN as usize {
+        return Err(DurableError::Io(io::ErrorKind::UnexpectedEof.into()));
+    }
+
+    let version = read_u32(log, LOG_MAGIC.len());
+    if version != LOG_VERSION {
+        return Err(DurableError::UnsupportedLogVersion(version));
+    }
+
+    let mut at = LOG_HEADER_LEN as usize;
+    while let Some(payload) = next_record(log, at) {
+        let key_len = read_u32(payload, 1) as usize;
+        let key = &payload[5..5 + key_len];
+
+        match payload[0] {
+            OP_INSERT => {
+                tree.insert_encoded(key, V::from(V::thaw(&payload[5 + key_len..])));
+            }
+            _ => {
+                tree.delete_encoded(key);
+            }
+        }
+        at += RECORD_HEADER_LEN + payload.len();
+    }
+
+    Ok(at)
+}
+
+/// Returns the payload of the record at `at` if it is complete and intact.
+fn next_record(log: &[u8], at: usize) -> Option<&[u8]> {
+    let header = log.get(at..at + RECORD_HEADER_LEN)?;
+    let len = read_u32(header, 0) as usize;
+    let payload = log.get(at + RECORD_HEADER_LEN..at + RECORD_HEADER_LEN + len)?;
+
+    let well_formed = crc32fast::hash(payload) == read_u32(header, 4)
+        && matches!(payload.first(), Some(&OP_INSERT | &OP_DELETE))
+        && payload.len() >= 5
+        && 5 + read_u32(payload, 1) as usize <= payload.len();
+    well_formed.then_some(payload)
+}
+
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    File::open(dir)?.sync_all()
+}
+
+/// Directories cannot be opened as files outside of unix.
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
@@ -0,0 +1,169 @@
+use crate::keys::ARTKey;
+use crate::tree::{insert_at, remove_at, value_at, value_ref_at, Slot};
+
+/// A view into a single entry of an `ARTree`, obtained with `ARTree::entry`.
+///
+/// The entry keeps the position reached by the descent, so inserting into a
+/// vacant entry or updating an occupied one never searches the tree again.
+pub enum Entry<'a, K: ARTKey, V> {
+    Vacant(VacantEntry<'a, K, V>),
+    Occupied(OccupiedEntry<'a, K, V>),
+}
+
+pub struct VacantEntry<'a, K: ARTKey, V> {
+    key: K,
+    link: Slot<'a, V>,
+    depth: usize,
+    split: Option<usize>,
+    len: &'a mut usize,
+}
+
+pub struct OccupiedEntry<'a, K: ARTKey, V> {
+    key: K,
+    link: Slot<'a, V>,
+    child: Option<u8>,
+    len: &'a mut usize,
+}
+
+impl<'a, K: ARTKey, V> Entry<'a, K, V> {
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default),
+        }
+    }
+
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    pub fn or_insert_with_key<F: FnOnce(&K) -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let value = default(entry.key());
+                entry.insert(value)
+            }
+        }
+    }
+
+    pub fn or_default(self) -> &'a mut V
+    where
+        V: Default,
+    {
+        self.or_insert_with(Default::default)
+    }
+
+    pub fn and_modify<F: FnOnce(&mut V)>(self, f: F) -> Self {
+        match self {
+            Entry::Occupied(mut entry) => {
+                f(entry.get_mut());
+                Entry::Occupied(entry)
+            }
+            Entry::Vacant(entry) => Entry::Vacant(entry),
+        }
+    }
+
+    /// Sets the value of the entry and returns an occupied entry for it.
+    pub fn insert(self, value: V) -> OccupiedEntry<'a, K, V> {
+        match self {
+            Entry::Occupied(mut entry) => {
+                entry.insert(value);
+                entry
+            }
+            Entry::Vacant(entry) => entry.insert_entry(value),
+        }
+    }
+
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+}
+
+impl<'a, K: ARTKey, V> VacantEntry<'a, K, V> {
+    pub(crate) fn new(key: K,
+                      link: Slot<'a, V>,
+                      depth: usize,
+                      split: Option<usize>,
+                      len: &'a mut usize) -> Self {
+        VacantEntry {
+            key,
+            link,
+            depth,
+            split,
+            len,
+        }
+    }
+
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    pub fn insert(self, value: V) -> &'a mut V {
+        self.insert_entry(value).into_mut()
+    }
+
+    fn insert_entry(mut self, value: V) -> OccupiedEntry<'a, K, V> {
+        let child = {
+            let bytes = self.key.convert_to_bytes();
+            insert_at(&mut self.link, bytes.as_ref(), self.depth, self.split, value)
+        };
+
+        *self.len += 1;
+        OccupiedEntry::new(self.key, self.link, child, self.len)
+    }
+}
+
+impl<'a, K: ARTKey, V> OccupiedEntry<'a, K, V> {
+    pub(crate) fn new(key: K,
+                      link: Slot<'a, V>,
+                      child: Option<u8>,
+                      len: &'a mut usize) -> Self {
+        OccupiedEntry {
+            key,
+            link,
+            child,
+            len,
+        }
+    }
+
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn get(&self) -> &V {
+        value_ref_at(self.link.node(), self.child)
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        value_at(self.link.node_mut(), self.child)
+    }
+
+    pub fn into_mut(self) -> &'a mut V {
+        value_at(self.link.into_node(), self.child)
+    }
+
+    pub fn insert(&mut self, value: V) -> V {
+        std::mem::replace(self.get_mut(), value)
+    }
+
+    pub fn remove(self) -> V {
+        self.remove_entry().1
+    }
+
+    pub fn remove_entry(mut self) -> (K, V) {
+        let value = remove_at(&mut self.link, self.child);
+        *self.len -= 1;
+        (self.key, value)
+    }
+}
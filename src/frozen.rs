@@ -0,0 +1,416 @@
+use crate::iter::{NodeRef, RawIter};
+use crate::keys::{ARTKey, ARTKeyDecode};
+use crate::node::key_mask;
+use crate::snapshot::{FrozenValue, SnapshotError, FOOTER_LEN, HEADER_LEN, MAGIC,
+                      TAG_INNER16, TAG_INNER256, TAG_INNER4, TAG_INNER48, TAG_LEAF, VERSION};
+use crate::tree::encode_bounds;
+
+use memmap2::Mmap;
+
+use std::borrow::Borrow;
+use std::fs::File;
+use std::iter::FusedIterator;
+use std::marker::PhantomData;
+use std::ops::RangeBounds;
+use std::path::Path;
+
+/// A read-only tree that works directly on a snapshot image written by
+/// `ARTree::write_snapshot`.
+///
+/// Nothing is deserialized: lookups and iteration decode nodes straight from
+/// the image as they reach them. Every read is bounds-checked and child links
+/// must point backwards, so a damaged image can never make reads loop or
+/// touch memory outside of the image. A node that does not fit the image, or
+/// claims more children than its type holds, reads as missing.
+pub struct FrozenARTree<K, V, B = Mmap> {
+    image: B,
+    root: usize,
+    len: usize,
+    _marker: PhantomData<fn() -> (K, V)>,
+}
+
+impl<K: ARTKey, V: FrozenValue> FrozenARTree<K, V, Mmap> {
+    /// Maps a snapshot file and verifies its checksum, which reads the whole
+    /// file once.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, SnapshotError> {
+        let tree = Self::open_unverified(path)?;
+        tree.verify()?;
+        Ok(tree)
+    }
+
+    /// Maps a snapshot file without reading it, for images that are trusted
+    /// or too large to checksum on every start.
+    ///
+    /// # Panics
+    ///
+    /// Damaged nodes read as missing, but value bytes are handed to
+    /// `FrozenValue::thaw` as they are. The implementations in this crate
+    /// panic on a value of the wrong length or, for `String`, on invalid
+    /// UTF-8.
+    pub fn open_unverified<P: AsRef<Path>>(path: P) -> Result<Self, SnapshotError> {
+        let file = File::open(path)?;
+        // SAFETY: the map is read-only. As with any mapped file, the caller
+        // must not truncate or rewrite the file while the tree is alive.
+        let image = unsafe { Mmap::map(&file)? };
+        Self::load(image)
+    }
+}
+
+impl<K: ARTKey, V: FrozenValue, B: AsRef<[u8]>> FrozenARTree<K, V, B> {
+    /// Reads a snapshot image that is already in memory, verifying its
+    /// checksum.
+    pub fn from_bytes(image: B) -> Result<Self, SnapshotError> {
+        let tree = Self::load(image)?;
+        tree.verify()?;
+        Ok(tree)
+    }
+
+    fn load(image: B) -> Result<Self, SnapshotError> {
+        let bytes = image.as_ref();
+
+        if bytes.len() < MAGIC.len() || bytes[..MAGIC.len()] != MAGIC {
+            return Err(SnapshotError::NotASnapshot);
+        }
+        if bytes.len() < HEADER_LEN + FOOTER_LEN {
+            return Err(SnapshotError::Truncated);
+        }
+
+        let version = read_u32(bytes, MAGIC.len()).expect("header length was checked");
+        if version != VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+
+        let footer = bytes.len() - FOOTER_LEN;
+        let len = read_u64(bytes, footer);
+        let root = read_u64(bytes, footer + 8);
+
+        if root >= footer as u64 || (root == 0) != (len == 0) {
+            return Err(SnapshotError::Truncated);
+        }
+
+        Ok(FrozenARTree {
+            root: root as usize,
+            len: len as usize,
+            image,
+            _marker: PhantomData,
+        })
+    }
+
+    fn verify(&self) -> Result<(), SnapshotError> {
+        let bytes = self.image.as_ref();
+        let end = bytes.len() - 4;
+
+        if Some(crc32fast::hash(&bytes[..end])) == read_u32(bytes, end) {
+            Ok(())
+        } else {
+            Err(SnapshotError::ChecksumMismatch)
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn root(&self) -> Option<FrozenNode<'_, V>> {
+        if self.root == 0 {
+            None
+        } else {
+            Some(FrozenNode::new(self.image.as_ref(), self.root))
+        }
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<V::Ref<'_>>
+    where
+        K: Borrow<Q>,
+        Q: ARTKey + ?Sized,
+    {
+        let key_bytes = key.convert_to_bytes();
+        let key_bytes = key_bytes.as_ref();
+        let mut current = self.root();
+        let mut depth: usize = 0;
+
+        while let Some(node) = current {
+            let layout = node.layout()?;
+            let rest = &key_bytes[depth..];
+
+            if layout.tag == TAG_LEAF {
+                return if layout.pkey == rest { layout.value.map(V::thaw) } else { None };
+            }
+            if !rest.starts_with(layout.pkey) {
+                return None;
+            }
+
+            depth += layout.pkey.len();
+            if depth == key_bytes.len() {
+                return layout.value.map(V::thaw);
+            }
+
+            current = node.find_child(&layout, key_bytes[depth]);
+            depth += 1;
+        }
+        None
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ARTKey + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    pub fn iter(&self) -> FrozenIter<'_, K, V> {
+        FrozenIter::new(self.raw_iter())
+    }
+
+    /// Iterates with the keys left encoded.
+    pub(crate) fn raw_iter(&self) -> RawIter<FrozenNode<'_, V>> {
+        RawIter::new(self.root())
+    }
+
+    /// Iterates in key order over all entries whose encoded key starts with
+    /// the encoding of `prefix`.
+    pub fn prefix_iter<P: ARTKey + ?Sized>(&self, prefix: &P) -> FrozenIter<'_, K, V> {
+        let bytes = prefix.convert_to_bytes();
+        let prefix_bytes = bytes.as_ref();
+        let mut current = self.root();
+        let mut depth: usize = 0;
+
+        while let Some(node) = current {
+            let Some(layout) = node.layout() else {
+                break;
+            };
+            let rest = &prefix_bytes[depth..];
+
+            // the prefix ends inside this node's compressed prefix
+            if rest.len() <= layout.pkey.len() {
+                if layout.pkey.starts_with(rest) {
+                    let path = prefix_bytes[..depth].to_vec();
+                    return FrozenIter::new(RawIter::new_at(Some(node), path));
+                }
+                break;
+            }
+
+            if layout.tag == TAG_LEAF || !rest.starts_with(layout.pkey) {
+                break;
+            }
+
+            depth += layout.pkey.len();
+            current = node.find_child(&layout, prefix_bytes[depth]);
+            depth += 1;
+        }
+
+        FrozenIter::new(RawIter::new_at(None, Vec::new()))
+    }
+
+    pub fn range<T, R>(&self, range: R) -> FrozenIter<'_, K, V>
+    where
+        K: Borrow<T>,
+        T: ARTKey + ?Sized,
+        R: RangeBounds<T>,
+    {
+        let (lower, upper) = encode_bounds(range);
+        FrozenIter::new(RawIter::with_bounds(self.root(), lower, upper))
+    }
+}
+
+/// A node inside of a snapshot image.
+pub(crate) struct FrozenNode<'a, V> {
+    image: &'a [u8],
+    offset: usize,
+    _marker: PhantomData<fn() -> V>,
+}
+
+/// The parts of a node that precede its children.
+struct Layout<'a> {
+    tag: u8,
+    pkey: &'a [u8],
+    value: Option<&'a [u8]>,
+    children_num: usize,
+    children_at: usize,
+}
+
+impl<'a, V> FrozenNode<'a, V> {
+    fn new(image: &'a [u8], offset: usize) -> Self {
+        FrozenNode {
+            image,
+            offset,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Reads the parts of the node before its children, or `None` if they
+    /// or the children do not fit the image.
+    fn layout(&self) -> Option<Layout<'a>> {
+        let image = self.image;
+        let tag = *image.get(self.offset)?;
+        let pkey_at = self.offset + 5;
+        let pkey = image.get(pkey_at..)?.get(..read_u32(image, self.offset + 1)? as usize)?;
+        let mut at = pkey_at + pkey.len();
+
+        let has_value = tag == TAG_LEAF || {
+            at += 1;
+            *image.get(at - 1)? != 0
+        };
+
+        let value = if has_value {
+            let value = image.get(at + 4..)?.get(..read_u32(image, at)? as usize)?;
+            at += 4 + value.len();
+            Some(value)
+        } else {
+            None
+        };
+
+        // the most children the node type holds, and the size of its body
+        let (capacity, body_len) = match tag {
+            TAG_LEAF => (0, 0),
+            TAG_INNER4 => (4, 4 + 4 * 8),
+            TAG_INNER16 => (16, 16 + 16 * 8),
+            TAG_INNER48 => (48, 256 + 48 * 8),
+            TAG_INNER256 => (256, 256 * 8),
+            _ => return None,
+        };
+
+        let children_num = if tag == TAG_LEAF { 0 } else { read_u16(image, at)? as usize };
+        let children_at = at + 2;
+
+        if children_num > capacity || (tag != TAG_LEAF && image.len() < children_at + body_len) {
+            return None;
+        }
+
+        Some(Layout {
+            tag,
+            pkey,
+            value,
+            children_num,
+            children_at,
+        })
+    }
+
+    /// Follows a child link, which must point backwards past the header.
+    fn child(&self, offset: u64) -> Option<Self> {
+        (HEADER_LEN as u64..self.offset as u64).contains(&offset)
+                                                .then(|| FrozenNode::new(self.image, offset as usize))
+    }
+
+    fn find_child(&self, layout: &Layout<'_>, key_byte: u8) -> Option<Self> {
+        let image = self.image;
+        let at = layout.children_at;
+        let num = layout.children_num;
+
+        let slot = match layout.tag {
+            TAG_INNER4 => {
+                let index = image[at..at + num].iter().position(|&key| key == key_byte)?;
+                at + 4 + index * 8
+            }
+            TAG_INNER16 => {
+                let keys = image[at..at + 16].try_into().unwrap();
+                let mask = key_mask(keys, key_byte) & ((1 << num) - 1);
+                if mask == 0 {
+                    return None;
+                }
+                at + 16 + mask.trailing_zeros() as usize * 8
+            }
+            TAG_INNER48 => match image[at + key_byte as usize] {
+                index if index < 48 => at + 256 + index as usize * 8,
+                _ => return None,
+            },
+            TAG_INNER256 => at + key_byte as usize * 8,
+            _ => return None,
+        };
+
+        self.child(read_u64(image, slot))
+    }
+
+    fn children(&self, layout: &Layout<'_>) -> Vec<(u8, Self)> {
+        let image = self.image;
+        let at = layout.children_at;
+        let num = layout.children_num;
+
+        match layout.tag {
+            TAG_INNER4 | TAG_INNER16 => {
+                let slots = if layout.tag == TAG_INNER4 { 4 } else { 16 };
+                (0..num).filter_map(|i| {
+                            Some((image[at + i], self.child(read_u64(image, at + slots + i * 8))?))
+                        })
+                        .collect()
+            }
+            TAG_INNER48 => (0..=u8::MAX)
+                .filter_map(|key_byte| match image[at + key_byte as usize] {
+                    index if index < 48 => {
+                        let offset = read_u64(image, at + 256 + index as usize * 8);
+                        Some((key_byte, self.child(offset)?))
+                    }
+                    _ => None,
+                })
+                .collect(),
+            TAG_INNER256 => (0..=u8::MAX)
+                .filter_map(|key_byte| {
+                    Some((key_byte, self.child(read_u64(image, at + key_byte as usize * 8))?))
+                })
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl<'a, V: FrozenValue> NodeRef for FrozenNode<'a, V> {
+    type Value = V::Ref<'a>;
+
+    fn pkey(&self) -> &[u8] {
+        self.layout().map_or(&[], |layout| layout.pkey)
+    }
+
+    fn open(self) -> (Option<Self::Value>, Vec<(u8, Self)>) {
+        match self.layout() {
+            Some(layout) => (layout.value.map(V::thaw), self.children(&layout)),
+            None => (None, Vec::new()),
+        }
+    }
+}
+
+pub struct FrozenIter<'a, K, V: FrozenValue> {
+    raw: RawIter<FrozenNode<'a, V>>,
+    _marker: PhantomData<K>,
+}
+
+impl<'a, K, V: FrozenValue> FrozenIter<'a, K, V> {
+    fn new(raw: RawIter<FrozenNode<'a, V>>) -> Self {
+        FrozenIter {
+            raw,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, K: ARTKeyDecode, V: FrozenValue> Iterator for FrozenIter<'a, K, V> {
+    type Item = (K, V::Ref<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.raw.next().map(|(key, val)| (K::convert_from_bytes(&key), val))
+    }
+}
+
+impl<'a, K: ARTKeyDecode, V: FrozenValue> DoubleEndedIterator for FrozenIter<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.raw.next_back().map(|(key, val)| (K::convert_from_bytes(&key), val))
+    }
+}
+
+impl<'a, K: ARTKeyDecode, V: FrozenValue> FusedIterator for FrozenIter<'a, K, V> {}
+
+fn read_u16(bytes: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(bytes.get(at..at + 2)?.try_into().unwrap()))
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(bytes.get(at..at + 4)?.try_into().unwrap()))
+}
+
+fn read_u64(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+}
@@ -0,0 +1,434 @@
+use crate::keys::{ARTKeyDecode, ByteKey};
+use crate::node::{ARTNode, InnerNode};
+
+use std::collections::VecDeque;
+use std::iter::FusedIterator;
+use std::marker::PhantomData;
+use std::ops::Bound;
+
+/// A borrowed handle to a subtree, either shared or exclusive.
+pub(crate) trait NodeRef: Sized {
+    type Value;
+
+    fn pkey(&self) -> &[u8];
+
+    /// Splits the node into its own value and its children in key byte order.
+    fn open(self) -> (Option<Self::Value>, Vec<(u8, Self)>);
+}
+
+impl<'a, V> NodeRef for &'a ARTNode<V> {
+    type Value = &'a V;
+
+    fn pkey(&self) -> &[u8] {
+        ARTNode::pkey(self)
+    }
+
+    fn open(self) -> (Option<Self::Value>, Vec<(u8, Self)>) {
+        match self {
+            ARTNode::Inner(inner, _, val) => (val.as_ref(), inner.sorted_children()),
+            ARTNode::Leaf(leaf) => (Some(leaf.value()), Vec::new()),
+        }
+    }
+}
+
+impl<'a, V> NodeRef for &'a mut ARTNode<V> {
+    type Value = &'a mut V;
+
+    fn pkey(&self) -> &[u8] {
+        ARTNode::pkey(self)
+    }
+
+    fn open(self) -> (Option<Self::Value>, Vec<(u8, Self)>) {
+        match self {
+            ARTNode::Inner(inner, _, val) => (val.as_mut(), inner.sorted_children_mut()),
+            ARTNode::Leaf(leaf) => (Some(leaf.value_mut()), Vec::new()),
+        }
+    }
+}
+
+impl<V> NodeRef for ARTNode<V> {
+    type Value = V;
+
+    fn pkey(&self) -> &[u8] {
+        ARTNode::pkey(self)
+    }
+
+    fn open(self) -> (Option<Self::Value>, Vec<(u8, Self)>) {
+        match self {
+            ARTNode::Inner(inner, _, val) => (val, inner.into_sorted_children()),
+            ARTNode::Leaf(leaf) => (Some(leaf.take_value()), Vec::new()),
+        }
+    }
+}
+
+enum Pending<N: NodeRef> {
+    Node(N),
+    Value(N::Value),
+}
+
+/// In-order traversal shared by all iterators.
+///
+/// The queue holds disjoint subtrees and values sorted by key. Subtrees are
+/// opened lazily from whichever end is consumed, so both ends can advance
+/// independently without ever handing out the same value twice.
+///
+/// Subtrees that lie entirely outside of the bounds are dropped as soon as
+/// their prefix is known, so only the nodes on the paths to the two bounds
+/// are ever opened partially.
+pub(crate) struct RawIter<N: NodeRef> {
+    queue: VecDeque<(ByteKey, Pending<N>)>,
+    lower: Bound<ByteKey>,
+    upper: Bound<ByteKey>,
+}
+
+impl<N: NodeRef> RawIter<N> {
+    pub(crate) fn new(root: Option<N>) -> Self {
+        Self::with_bounds(root, Bound::Unbounded, Bound::Unbounded)
+    }
+
+    /// Iterates over a subtree only; `path` holds the key bytes leading up
+    /// to the subtree's compressed prefix.
+    pub(crate) fn new_at(subtree: Option<N>, path: ByteKey) -> Self {
+        let mut queue = VecDeque::new();
+
+        if let Some(node) = subtree {
+            let mut key = path;
+            key.extend_from_slice(node.pkey());
+            queue.push_back((key, Pending::Node(node)));
+        }
+
+        RawIter {
+            queue,
+            lower: Bound::Unbounded,
+            upper: Bound::Unbounded,
+        }
+    }
+
+    pub(crate) fn with_bounds(root: Option<N>, lower: Bound<ByteKey>, upper: Bound<ByteKey>)
+        -> Self
+    {
+        let mut iter = RawIter {
+            queue: VecDeque::new(),
+            lower,
+            upper,
+        };
+
+        if let Some(node) = root {
+            let key = node.pkey().to_vec();
+            if iter.overlaps(&key) {
+                iter.queue.push_back((key, Pending::Node(node)));
+            }
+        }
+
+        iter
+    }
+
+    /// Whether a subtree whose keys all start with `prefix` may hold keys
+    /// within the bounds.
+    fn overlaps(&self, prefix: &[u8]) -> bool {
+        let below = match &self.lower {
+            Bound::Included(lower) | Bound::Excluded(lower) => {
+                prefix < lower.as_slice() && !lower.starts_with(prefix)
+            }
+            Bound::Unbounded => false,
+        };
+
+        let above = match &self.upper {
+            Bound::Included(upper) => prefix > upper.as_slice(),
+            Bound::Excluded(upper) => prefix >= upper.as_slice(),
+            Bound::Unbounded => false,
+        };
+
+        !below && !above
+    }
+
+    fn contains(&self, key: &[u8]) -> bool {
+        let after_lower = match &self.lower {
+            Bound::Included(lower) => key >= lower.as_slice(),
+            Bound::Excluded(lower) => key > lower.as_slice(),
+            Bound::Unbounded => true,
+        };
+
+        let before_upper = match &self.upper {
+            Bound::Included(upper) => key <= upper.as_slice(),
+            Bound::Excluded(upper) => key < upper.as_slice(),
+            Bound::Unbounded => true,
+        };
+
+        after_lower && before_upper
+    }
+
+    fn child_entries(&self, key: &[u8], children: Vec<(u8, N)>) -> Vec<(ByteKey, Pending<N>)> {
+        children.into_iter().filter_map(|(byte, child)| {
+            let pkey = child.pkey();
+            let mut child_key = Vec::with_capacity(key.len() + 1 + pkey.len());
+            child_key.extend_from_slice(key);
+            child_key.push(byte);
+            child_key.extend_from_slice(pkey);
+
+            if self.overlaps(&child_key) {
+                Some((child_key, Pending::Node(child)))
+            } else {
+                None
+            }
+        }).collect()
+    }
+
+    pub(crate) fn next(&mut self) -> Option<(ByteKey, N::Value)> {
+        loop {
+            match self.queue.pop_front()? {
+                (key, Pending::Value(val)) => return Some((key, val)),
+                (key, Pending::Node(node)) => {
+                    let (val, children) = node.open();
+
+                    for entry in self.child_entries(&key, children).into_iter().rev() {
+                        self.queue.push_front(entry);
+                    }
+
+                    // a node's own value precedes everything below it
+                    match val {
+                        Some(val) if self.contains(&key) => return Some((key, val)),
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+
+    pub(crate) fn next_back(&mut self) -> Option<(ByteKey, N::Value)> {
+        loop {
+            match self.queue.pop_back()? {
+                (key, Pending::Value(val)) => return Some((key, val)),
+                (key, Pending::Node(node)) => {
+                    let (val, children) = node.open();
+                    let entries = self.child_entries(&key, children);
+
+                    match val {
+                        Some(val) if self.contains(&key) => {
+                            if entries.is_empty() {
+                                return Some((key, val));
+                            }
+                            self.queue.push_back((key, Pending::Value(val)));
+                        }
+                        _ => {}
+                    }
+
+                    self.queue.extend(entries);
+                }
+            }
+        }
+    }
+}
+
+pub struct Iter<'a, K, V> {
+    raw: RawIter<&'a ARTNode<V>>,
+    _marker: PhantomData<K>,
+}
+
+pub struct IterMut<'a, K, V> {
+    raw: RawIter<&'a mut ARTNode<V>>,
+    _marker: PhantomData<K>,
+}
+
+pub struct IntoIter<K, V> {
+    raw: RawIter<ARTNode<V>>,
+    _marker: PhantomData<K>,
+}
+
+pub struct Range<'a, K, V> {
+    raw: RawIter<&'a ARTNode<V>>,
+    _marker: PhantomData<K>,
+}
+
+pub struct RangeMut<'a, K, V> {
+    raw: RawIter<&'a mut ARTNode<V>>,
+    _marker: PhantomData<K>,
+}
+
+pub struct Keys<'a, K, V> {
+    inner: Iter<'a, K, V>,
+}
+
+pub struct Values<'a, K, V> {
+    inner: Iter<'a, K, V>,
+}
+
+impl<'a, K, V> Iter<'a, K, V> {
+    pub(crate) fn new(root: Option<&'a ARTNode<V>>) -> Self {
+        Iter {
+            raw: RawIter::new(root),
+            _marker: PhantomData,
+        }
+    }
+
+    pub(crate) fn new_at(subtree: Option<&'a ARTNode<V>>, path: ByteKey) -> Self {
+        Iter {
+            raw: RawIter::new_at(subtree, path),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, K, V> IterMut<'a, K, V> {
+    pub(crate) fn new(root: Option<&'a mut ARTNode<V>>) -> Self {
+        IterMut {
+            raw: RawIter::new(root),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<K, V> IntoIter<K, V> {
+    pub(crate) fn new(root: Option<ARTNode<V>>) -> Self {
+        IntoIter {
+            raw: RawIter::new(root),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, K, V> Range<'a, K, V> {
+    pub(crate) fn new(root: Option<&'a ARTNode<V>>,
+                      lower: Bound<ByteKey>,
+                      upper: Bound<ByteKey>) -> Self {
+        Range {
+            raw: RawIter::with_bounds(root, lower, upper),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, K, V> RangeMut<'a, K, V> {
+    pub(crate) fn new(root: Option<&'a mut ARTNode<V>>,
+                      lower: Bound<ByteKey>,
+                      upper: Bound<ByteKey>) -> Self {
+        RangeMut {
+            raw: RawIter::with_bounds(root, lower, upper),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, K, V> Keys<'a, K, V> {
+    pub(crate) fn new(inner: Iter<'a, K, V>) -> Self {
+        Keys { inner }
+    }
+}
+
+impl<'a, K, V> Values<'a, K, V> {
+    pub(crate) fn new(inner: Iter<'a, K, V>) -> Self {
+        Values { inner }
+    }
+}
+
+impl<'a, K: ARTKeyDecode, V> Iterator for Iter<'a, K, V> {
+    type Item = (K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.raw.next().map(|(key, val)| (K::convert_from_bytes(&key), val))
+    }
+}
+
+impl<'a, K: ARTKeyDecode, V> DoubleEndedIterator for Iter<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.raw.next_back().map(|(key, val)| (K::convert_from_bytes(&key), val))
+    }
+}
+
+impl<'a, K: ARTKeyDecode, V> FusedIterator for Iter<'a, K, V> {}
+
+impl<'a, K: ARTKeyDecode, V> Iterator for IterMut<'a, K, V> {
+    type Item = (K, &'a mut V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.raw.next().map(|(key, val)| (K::convert_from_bytes(&key), val))
+    }
+}
+
+impl<'a, K: ARTKeyDecode, V> DoubleEndedIterator for IterMut<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.raw.next_back().map(|(key, val)| (K::convert_from_bytes(&key), val))
+    }
+}
+
+impl<'a, K: ARTKeyDecode, V> FusedIterator for IterMut<'a, K, V> {}
+
+impl<K: ARTKeyDecode, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.raw.next().map(|(key, val)| (K::convert_from_bytes(&key), val))
+    }
+}
+
+impl<K: ARTKeyDecode, V> DoubleEndedIterator for IntoIter<K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.raw.next_back().map(|(key, val)| (K::convert_from_bytes(&key), val))
+    }
+}
+
+impl<K: ARTKeyDecode, V> FusedIterator for IntoIter<K, V> {}
+
+impl<'a, K: ARTKeyDecode, V> Iterator for Range<'a, K, V> {
+    type Item = (K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.raw.next().map(|(key, val)| (K::convert_from_bytes(&key), val))
+    }
+}
+
+impl<'a, K: ARTKeyDecode, V> DoubleEndedIterator for Range<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.raw.next_back().map(|(key, val)| (K::convert_from_bytes(&key), val))
+    }
+}
+
+impl<'a, K: ARTKeyDecode, V> FusedIterator for Range<'a, K, V> {}
+
+impl<'a, K: ARTKeyDecode, V> Iterator for RangeMut<'a, K, V> {
+    type Item = (K, &'a mut V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.raw.next().map(|(key, val)| (K::convert_from_bytes(&key), val))
+    }
+}
+
+impl<'a, K: ARTKeyDecode, V> DoubleEndedIterator for RangeMut<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.raw.next_back().map(|(key, val)| (K::convert_from_bytes(&key), val))
+    }
+}
+
+impl<'a, K: ARTKeyDecode, V> FusedIterator for RangeMut<'a, K, V> {}
+
+impl<'a, K: ARTKeyDecode, V> Iterator for Keys<'a, K, V> {
+    type Item = K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(key, _)| key)
+    }
+}
+
+impl<'a, K: ARTKeyDecode, V> DoubleEndedIterator for Keys<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|(key, _)| key)
+    }
+}
+
+impl<'a, K: ARTKeyDecode, V> FusedIterator for Keys<'a, K, V> {}
+
+impl<'a, K, V> Iterator for Values<'a, K, V> {
+    type Item = &'a V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.raw.next().map(|(_, val)| val)
+    }
+}
+
+impl<'a, K, V> DoubleEndedIterator for Values<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.raw.next_back().map(|(_, val)| val)
+    }
+}
+
+impl<'a, K, V> FusedIterator for Values<'a, K, V> {}
@@ -1,43 +1,408 @@
 use std::cmp::Ordering;
 use std::iter::zip;
+use std::num::{
+    NonZeroI128, NonZeroI16, NonZeroI32, NonZeroI64, NonZeroI8, NonZeroIsize,
+    NonZeroU128, NonZeroU16, NonZeroU32, NonZeroU64, NonZeroU8, NonZeroUsize,
+};
+
+#[cfg(feature = "derive")]
+pub use rust_art_derive::{ARTKey, ARTKeyDecode};
 
 pub type ByteKey = Vec<u8>;
 
+/// Maps a key onto bytes whose lexicographic order matches the key's order.
+///
+/// Types that borrow as one another (e.g. `String` and `str`) must produce
+/// the same bytes, so that a tree keyed by one can be queried with the other.
 pub trait ARTKey {
-    type Bytes: AsRef<[u8]>;
+    type Bytes<'a>: AsRef<[u8]> where Self: 'a;
+
+    fn convert_to_bytes(&self) -> Self::Bytes<'_>;
+
+    /// Appends the key as one part of a composite key.
+    ///
+    /// The default is only correct for fixed-width encodings. Variable-width
+    /// keys must escape and terminate their bytes, so that no encoding is a
+    /// prefix of another and concatenations keep the order of tuples.
+    fn encode_component(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self.convert_to_bytes().as_ref());
+    }
+}
+
+/// Rebuilds a key from the bytes produced by `ARTKey::convert_to_bytes`.
+pub trait ARTKeyDecode: ARTKey + Sized {
+    fn convert_from_bytes(bytes: &[u8]) -> Self;
+
+    /// Splits a part written by `ARTKey::encode_component` off the front of a
+    /// composite key, returning it together with the remaining bytes.
+    fn decode_component(bytes: &[u8]) -> (Self, &[u8]);
+}
+
+// Inside a composite key a zero byte is written as 0x00 0xFF and the part is
+// terminated by 0x00 0x00, which sorts before any continuation.
+fn encode_var_component(bytes: &[u8], out: &mut Vec<u8>) {
+    for &byte in bytes {
+        out.push(byte);
+        if byte == 0 {
+            out.push(0xFF);
+        }
+    }
+    out.extend_from_slice(&[0, 0]);
+}
+
+fn decode_var_component(bytes: &[u8]) -> (Vec<u8>, &[u8]) {
+    let mut part = Vec::new();
+    let mut i = 0;
+
+    loop {
+        match bytes.get(i..i + 2) {
+            Some([0, 0]) => return (part, &bytes[i + 2..]),
+            Some([0, _]) => {
+                part.push(0);
+                i += 2;
+            }
+            _ => {
+                part.push(*bytes.get(i).expect("unterminated key component"));
+                i += 1;
+            }
+        }
+    }
+}
 
-    fn convert_to_bytes(self) -> Self::Bytes;
+fn split_fixed_component<const N: usize>(bytes: &[u8]) -> (&[u8], &[u8]) {
+    assert!(bytes.len() >= N, "truncated key component");
+    bytes.split_at(N)
 }
 
 impl ARTKey for String {
-    type Bytes = Vec<u8>;
+    type Bytes<'a> = &'a [u8];
+
+    fn convert_to_bytes(&self) -> Self::Bytes<'_> {
+        self.as_bytes()
+    }
+
+    fn encode_component(&self, out: &mut Vec<u8>) {
+        encode_var_component(self.convert_to_bytes(), out);
+    }
+}
+
+impl ARTKey for str {
+    type Bytes<'a> = &'a [u8];
+
+    fn convert_to_bytes(&self) -> Self::Bytes<'_> {
+        self.as_bytes()
+    }
+
+    fn encode_component(&self, out: &mut Vec<u8>) {
+        encode_var_component(self.convert_to_bytes(), out);
+    }
+}
+
+impl ARTKey for [u8] {
+    type Bytes<'a> = &'a [u8];
+
+    fn convert_to_bytes(&self) -> Self::Bytes<'_> {
+        self
+    }
+
+    fn encode_component(&self, out: &mut Vec<u8>) {
+        encode_var_component(self.convert_to_bytes(), out);
+    }
+}
 
-    fn convert_to_bytes(self) -> Self::Bytes {
-        self.into_bytes()
+impl ARTKey for Vec<u8> {
+    type Bytes<'a> = &'a [u8];
+
+    fn convert_to_bytes(&self) -> Self::Bytes<'_> {
+        self
+    }
+
+    fn encode_component(&self, out: &mut Vec<u8>) {
+        encode_var_component(self.convert_to_bytes(), out);
+    }
+}
+
+impl ARTKeyDecode for Vec<u8> {
+    fn convert_from_bytes(bytes: &[u8]) -> Self {
+        bytes.to_vec()
+    }
+
+    fn decode_component(bytes: &[u8]) -> (Self, &[u8]) {
+        decode_var_component(bytes)
+    }
+}
+
+impl ARTKey for Box<[u8]> {
+    type Bytes<'a> = &'a [u8];
+
+    fn convert_to_bytes(&self) -> Self::Bytes<'_> {
+        self
+    }
+
+    fn encode_component(&self, out: &mut Vec<u8>) {
+        encode_var_component(self.convert_to_bytes(), out);
+    }
+}
+
+impl ARTKeyDecode for Box<[u8]> {
+    fn convert_from_bytes(bytes: &[u8]) -> Self {
+        bytes.into()
+    }
+
+    fn decode_component(bytes: &[u8]) -> (Self, &[u8]) {
+        let (part, rest) = decode_var_component(bytes);
+        (part.into_boxed_slice(), rest)
+    }
+}
+
+impl<const N: usize> ARTKey for [u8; N] {
+    type Bytes<'a> = &'a [u8];
+
+    fn convert_to_bytes(&self) -> Self::Bytes<'_> {
+        self
+    }
+}
+
+impl<const N: usize> ARTKeyDecode for [u8; N] {
+    fn convert_from_bytes(bytes: &[u8]) -> Self {
+        bytes.try_into().expect("invalid key length")
+    }
+
+    fn decode_component(bytes: &[u8]) -> (Self, &[u8]) {
+        let (part, rest) = split_fixed_component::<N>(bytes);
+        (Self::convert_from_bytes(part), rest)
+    }
+}
+
+impl<T: ARTKey + ?Sized> ARTKey for &T {
+    type Bytes<'a> = T::Bytes<'a> where Self: 'a;
+
+    fn convert_to_bytes(&self) -> Self::Bytes<'_> {
+        (**self).convert_to_bytes()
+    }
+
+    fn encode_component(&self, out: &mut Vec<u8>) {
+        (**self).encode_component(out)
+    }
+}
+
+impl ARTKey for bool {
+    type Bytes<'a> = [u8; 1];
+
+    fn convert_to_bytes(&self) -> Self::Bytes<'_> {
+        [*self as u8]
+    }
+}
+
+impl ARTKeyDecode for bool {
+    fn convert_from_bytes(bytes: &[u8]) -> Self {
+        u8::convert_from_bytes(bytes) != 0
+    }
+
+    fn decode_component(bytes: &[u8]) -> (Self, &[u8]) {
+        let (part, rest) = split_fixed_component::<1>(bytes);
+        (Self::convert_from_bytes(part), rest)
+    }
+}
+
+impl ARTKey for char {
+    type Bytes<'a> = [u8; 4];
+
+    fn convert_to_bytes(&self) -> Self::Bytes<'_> {
+        (*self as u32).to_be_bytes()
+    }
+}
+
+impl ARTKeyDecode for char {
+    fn convert_from_bytes(bytes: &[u8]) -> Self {
+        char::from_u32(u32::convert_from_bytes(bytes)).expect("invalid char key")
+    }
+
+    fn decode_component(bytes: &[u8]) -> (Self, &[u8]) {
+        let (part, rest) = split_fixed_component::<4>(bytes);
+        (Self::convert_from_bytes(part), rest)
+    }
+}
+
+impl ARTKeyDecode for String {
+    fn convert_from_bytes(bytes: &[u8]) -> Self {
+        String::from_utf8(bytes.to_vec()).expect("String keys are encoded as UTF-8")
+    }
+
+    fn decode_component(bytes: &[u8]) -> (Self, &[u8]) {
+        let (part, rest) = decode_var_component(bytes);
+        (String::from_utf8(part).expect("String keys are encoded as UTF-8"), rest)
     }
 }
 
 macro_rules! ArtKeyNumImpl {
     ($sty: ty) => {
         impl ARTKey for $sty {
-            type Bytes = [u8; std::mem::size_of::<$sty>()];
-            fn convert_to_bytes(self) -> Self::Bytes {
+            type Bytes<'a> = [u8; std::mem::size_of::<$sty>()];
+            fn convert_to_bytes(&self) -> Self::Bytes<'_> {
                 self.to_be_bytes()
             }
         }
+
+        impl ARTKeyDecode for $sty {
+            fn convert_from_bytes(bytes: &[u8]) -> Self {
+                <$sty>::from_be_bytes(bytes.try_into().expect("invalid key length"))
+            }
+
+            fn decode_component(bytes: &[u8]) -> (Self, &[u8]) {
+                let (part, rest) = split_fixed_component::<{ std::mem::size_of::<$sty>() }>(bytes);
+                (Self::convert_from_bytes(part), rest)
+            }
+        }
     };
 }
 
+// Flipping the sign bit maps the two's complement range onto the unsigned
+// one while keeping the order, so negative numbers sort before positive ones.
+macro_rules! ArtKeySignedImpl {
+    ($sty: ty, $uty: ty) => {
+        impl ARTKey for $sty {
+            type Bytes<'a> = [u8; std::mem::size_of::<$sty>()];
+            fn convert_to_bytes(&self) -> Self::Bytes<'_> {
+                ((*self as $uty) ^ (1 << (<$uty>::BITS - 1))).to_be_bytes()
+            }
+        }
+
+        impl ARTKeyDecode for $sty {
+            fn convert_from_bytes(bytes: &[u8]) -> Self {
+                let bits = <$uty>::from_be_bytes(bytes.try_into().expect("invalid key length"));
+                (bits ^ (1 << (<$uty>::BITS - 1))) as $sty
+            }
+
+            fn decode_component(bytes: &[u8]) -> (Self, &[u8]) {
+                let (part, rest) = split_fixed_component::<{ std::mem::size_of::<$sty>() }>(bytes);
+                (Self::convert_from_bytes(part), rest)
+            }
+        }
+    };
+}
+
+// IEEE-754 total order: positive floats get their sign bit set, negative
+// floats get all bits inverted so that larger magnitudes sort first.
+macro_rules! ArtKeyFloatImpl {
+    ($fty: ty, $uty: ty) => {
+        impl ARTKey for $fty {
+            type Bytes<'a> = [u8; std::mem::size_of::<$fty>()];
+            fn convert_to_bytes(&self) -> Self::Bytes<'_> {
+                const SIGN: $uty = 1 << (<$uty>::BITS - 1);
+                let bits = self.to_bits();
+                let ordered = if bits & SIGN == 0 { bits | SIGN } else { !bits };
+                ordered.to_be_bytes()
+            }
+        }
+
+        impl ARTKeyDecode for $fty {
+            fn convert_from_bytes(bytes: &[u8]) -> Self {
+                const SIGN: $uty = 1 << (<$uty>::BITS - 1);
+                let ordered = <$uty>::from_be_bytes(bytes.try_into().expect("invalid key length"));
+                let bits = if ordered & SIGN != 0 { ordered & !SIGN } else { !ordered };
+                <$fty>::from_bits(bits)
+            }
+
+            fn decode_component(bytes: &[u8]) -> (Self, &[u8]) {
+                let (part, rest) = split_fixed_component::<{ std::mem::size_of::<$fty>() }>(bytes);
+                (Self::convert_from_bytes(part), rest)
+            }
+        }
+    };
+}
+
+macro_rules! ArtKeyNonZeroImpl {
+    ($nzty: ty, $ty: ty) => {
+        impl ARTKey for $nzty {
+            type Bytes<'a> = <$ty as ARTKey>::Bytes<'a>;
+            fn convert_to_bytes(&self) -> Self::Bytes<'_> {
+                self.get().convert_to_bytes()
+            }
+        }
+
+        impl ARTKeyDecode for $nzty {
+            fn convert_from_bytes(bytes: &[u8]) -> Self {
+                <$nzty>::new(<$ty>::convert_from_bytes(bytes)).expect("zero key for a NonZero type")
+            }
+
+            fn decode_component(bytes: &[u8]) -> (Self, &[u8]) {
+                let (part, rest) = split_fixed_component::<{ std::mem::size_of::<$nzty>() }>(bytes);
+                (Self::convert_from_bytes(part), rest)
+            }
+        }
+    };
+}
+
+ArtKeyNumImpl!(u8);
 ArtKeyNumImpl!(u16);
 ArtKeyNumImpl!(u32);
 ArtKeyNumImpl!(u64);
-ArtKeyNumImpl!(i16);
-ArtKeyNumImpl!(i32);
-ArtKeyNumImpl!(i64);
+ArtKeyNumImpl!(u128);
 ArtKeyNumImpl!(usize);
-ArtKeyNumImpl!(isize);
-ArtKeyNumImpl!(f32);
-ArtKeyNumImpl!(f64);
+ArtKeySignedImpl!(i8, u8);
+ArtKeySignedImpl!(i16, u16);
+ArtKeySignedImpl!(i32, u32);
+ArtKeySignedImpl!(i64, u64);
+ArtKeySignedImpl!(i128, u128);
+ArtKeySignedImpl!(isize, usize);
+ArtKeyFloatImpl!(f32, u32);
+ArtKeyFloatImpl!(f64, u64);
+ArtKeyNonZeroImpl!(NonZeroU8, u8);
+ArtKeyNonZeroImpl!(NonZeroU16, u16);
+ArtKeyNonZeroImpl!(NonZeroU32, u32);
+ArtKeyNonZeroImpl!(NonZeroU64, u64);
+ArtKeyNonZeroImpl!(NonZeroU128, u128);
+ArtKeyNonZeroImpl!(NonZeroUsize, usize);
+ArtKeyNonZeroImpl!(NonZeroI8, i8);
+ArtKeyNonZeroImpl!(NonZeroI16, i16);
+ArtKeyNonZeroImpl!(NonZeroI32, i32);
+ArtKeyNonZeroImpl!(NonZeroI64, i64);
+ArtKeyNonZeroImpl!(NonZeroI128, i128);
+ArtKeyNonZeroImpl!(NonZeroIsize, isize);
+
+// Every element is written as a component, including the last one, so a
+// tuple is itself a valid component and shorter tuples act as prefixes.
+macro_rules! ArtKeyTupleImpl {
+    ($($ty: ident $var: ident),+) => {
+        impl<$($ty: ARTKey),+> ARTKey for ($($ty,)+) {
+            type Bytes<'a> = Vec<u8> where Self: 'a;
+
+            fn convert_to_bytes(&self) -> Self::Bytes<'_> {
+                let mut bytes = Vec::new();
+                self.encode_component(&mut bytes);
+                bytes
+            }
+
+            fn encode_component(&self, out: &mut Vec<u8>) {
+                let ($($var,)+) = self;
+                $($var.encode_component(out);)+
+            }
+        }
+
+        impl<$($ty: ARTKeyDecode),+> ARTKeyDecode for ($($ty,)+) {
+            fn convert_from_bytes(bytes: &[u8]) -> Self {
+                let (key, rest) = Self::decode_component(bytes);
+                assert!(rest.is_empty(), "invalid key length");
+                key
+            }
+
+            fn decode_component(bytes: &[u8]) -> (Self, &[u8]) {
+                $(let ($var, bytes) = $ty::decode_component(bytes);)+
+                (($($var,)+), bytes)
+            }
+        }
+    };
+}
+
+ArtKeyTupleImpl!(A a);
+ArtKeyTupleImpl!(A a, B b);
+ArtKeyTupleImpl!(A a, B b, C c);
+ArtKeyTupleImpl!(A a, B b, C c, D d);
+ArtKeyTupleImpl!(A a, B b, C c, D d, E e);
+ArtKeyTupleImpl!(A a, B b, C c, D d, E e, F f);
+ArtKeyTupleImpl!(A a, B b, C c, D d, E e, F f, G g);
+ArtKeyTupleImpl!(A a, B b, C c, D d, E e, F f, G g, H h);
 
 
 pub enum PartialKeyComp {
@@ -1,24 +1,60 @@
 #![deny(rust_2018_idioms)]
-#![feature(new_uninit, portable_simd)]
+#![cfg_attr(feature = "nightly-simd", feature(portable_simd))]
 
+// lets the derive macros' `::rust_art` paths resolve in the crate's own tests
+#[cfg(test)]
+extern crate self as rust_art;
+
+pub mod build;
+pub mod concurrent;
+pub mod durable;
+pub mod entry;
+pub mod frozen;
+pub mod iter;
 pub mod keys;
+pub mod mvcc;
 pub mod node;
+pub mod persistent;
+pub mod set;
+pub mod snapshot;
+pub mod transaction;
 pub mod tree;
 
+#[cfg(feature = "serde")]
+mod serde;
+
 use keys::ARTKey;
 use node::ARTLink;
 use std::marker::PhantomData;
 
 pub struct ARTree<K: ARTKey, V> {
     root: ARTLink<V>,
+    len: usize,
     _marker: PhantomData<K>,
 }
 
 #[cfg(test)]
 mod tests {
     use crate::ARTree;
+    use crate::build::BulkLoadError;
+    use crate::concurrent::{ConcurrentARTree, RowexARTree};
+    use crate::durable::{DurableARTree, SyncPolicy};
+    use crate::entry::Entry;
+    use crate::frozen::FrozenARTree;
+    use crate::node::{ARTInnerNode, ARTNode, InnerNode};
+    use crate::persistent::{PersistentARTree, PersistentNode};
+    use crate::set::ARTSet;
+    use crate::keys::ARTKeyDecode;
+    use crate::mvcc::MvccARTree;
+    use crate::snapshot::SnapshotError;
     use rand_pcg::Pcg64;
     use rand::{ SeedableRng, Rng };
+    use std::collections::BTreeMap;
+    use std::collections::hash_map::DefaultHasher;
+    use std::fmt::Debug;
+    use std::hash::{Hash, Hasher};
+    use std::num::NonZeroI32;
+    use std::ops::Bound;
 
     #[test]
     fn string_art() {
@@ -48,37 +84,37 @@ mod tests {
         art.insert(String::from("Jen"), 50);
         art.insert(String::from("Wendell"), 50);
 
-        assert_eq!(26, *art.get(String::from("Jason")).unwrap());
-        assert_eq!(21, *art.get(String::from("Drake")).unwrap());
-        assert_eq!(54, *art.get(String::from("Nathaniel")).unwrap());
-        assert_eq!(22, *art.get(String::from("Velma")).unwrap());
-        assert_eq!(55, *art.get(String::from("Sabrina")).unwrap());
-        assert_eq!(44, *art.get(String::from("Rusty")).unwrap());
-        assert_eq!(23, *art.get(String::from("Jerry")).unwrap());
-        assert_eq!(23, *art.get(String::from("Jenny")).unwrap());
-        assert_eq!(23, *art.get(String::from("Jenson")).unwrap());
-        assert_eq!(50, *art.get(String::from("Jen")).unwrap());
-        assert_eq!(50, *art.get(String::from("Wendell")).unwrap());
-
-        art.delete(String::from("Jenny"));
-        art.delete(String::from("Jason"));
-        art.delete(String::from("Jen"));
-        art.delete(String::from("Caleb"));
-        art.delete(String::from("Drake"));
-
-        assert_eq!(None, art.get(String::from("Drake")));
-        assert_eq!(None, art.get(String::from("Jenny")));
-        assert_eq!(None, art.get(String::from("Jason")));
-        assert_eq!(None, art.get(String::from("Jen")));
-        assert_eq!(None, art.get(String::from("Caleb")));
-
-        assert_eq!(54, *art.get(String::from("Nathaniel")).unwrap());
-        assert_eq!(22, *art.get(String::from("Velma")).unwrap());
-        assert_eq!(55, *art.get(String::from("Sabrina")).unwrap());
-        assert_eq!(44, *art.get(String::from("Rusty")).unwrap());
-        assert_eq!(23, *art.get(String::from("Jerry")).unwrap());
-        assert_eq!(23, *art.get(String::from("Jenson")).unwrap());
-        assert_eq!(50, *art.get(String::from("Wendell")).unwrap());
+        assert_eq!(26, *art.get("Jason").unwrap());
+        assert_eq!(21, *art.get("Drake").unwrap());
+        assert_eq!(54, *art.get("Nathaniel").unwrap());
+        assert_eq!(22, *art.get("Velma").unwrap());
+        assert_eq!(55, *art.get("Sabrina").unwrap());
+        assert_eq!(44, *art.get("Rusty").unwrap());
+        assert_eq!(23, *art.get("Jerry").unwrap());
+        assert_eq!(23, *art.get("Jenny").unwrap());
+        assert_eq!(23, *art.get("Jenson").unwrap());
+        assert_eq!(50, *art.get("Jen").unwrap());
+        assert_eq!(50, *art.get("Wendell").unwrap());
+
+        art.delete("Jenny");
+        art.delete("Jason");
+        art.delete("Jen");
+        art.delete("Caleb");
+        art.delete("Drake");
+
+        assert_eq!(None, art.get("Drake"));
+        assert_eq!(None, art.get("Jenny"));
+        assert_eq!(None, art.get("Jason"));
+        assert_eq!(None, art.get("Jen"));
+        assert_eq!(None, art.get("Caleb"));
+
+        assert_eq!(54, *art.get("Nathaniel").unwrap());
+        assert_eq!(22, *art.get("Velma").unwrap());
+        assert_eq!(55, *art.get("Sabrina").unwrap());
+        assert_eq!(44, *art.get("Rusty").unwrap());
+        assert_eq!(23, *art.get("Jerry").unwrap());
+        assert_eq!(23, *art.get("Jenson").unwrap());
+        assert_eq!(50, *art.get("Wendell").unwrap());
     }
 
 
@@ -87,7 +123,9 @@ mod tests {
         const SEED: u64 = 10;
 
         let mut rng = Pcg64::seed_from_u64(SEED);
-        let mut keys: Vec<u64> = vec![0; 100_000];
+        // Miri is orders of magnitude slower, so it runs a smaller sample
+        let key_count = if cfg!(miri) { 1_000 } else { 100_000 };
+        let mut keys: Vec<u64> = vec![0; key_count];
         rng.fill(&mut keys[..]);
         let mut art = ARTree::<u64, u64>::new();
 
@@ -97,23 +135,1305 @@ mod tests {
         }
 
         for &key in keys.iter() {
-            assert_eq!(key + 1, *art.get(key).unwrap());
+            assert_eq!(key + 1, *art.get(&key).unwrap());
         }
 
         const SEED_DEL: u64 = 13;
 
         for &key in keys.iter() {
             if key % SEED_DEL == 0 {
-                art.delete(key);
+                art.delete(&key);
             }
         }
 
         for &key in keys.iter() {
             if key % SEED_DEL == 0 {
-                assert_eq!(None, art.get(key));
+                assert_eq!(None, art.get(&key));
             } else {
-                assert_eq!(key + 1, *art.get(key).unwrap());
+                assert_eq!(key + 1, *art.get(&key).unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn ordered_iteration() {
+        const SEED: u64 = 21;
+
+        let mut rng = Pcg64::seed_from_u64(SEED);
+        let mut keys: Vec<u64> = vec![0; 10_000];
+        rng.fill(&mut keys[..]);
+        let mut art = ARTree::<u64, u64>::new();
+
+        for &key in keys.iter() {
+            art.insert(key, key + 1);
+        }
+
+        keys.sort_unstable();
+        keys.dedup();
+
+        assert!(art.keys().eq(keys.iter().copied()));
+        assert!(art.values().eq(keys.iter().map(|key| key + 1).collect::<Vec<_>>().iter()));
+        assert!(art.iter().rev().map(|(key, _)| key).eq(keys.iter().rev().copied()));
+
+        for (key, val) in art.iter_mut() {
+            *val = key / 2;
+        }
+
+        for (key, &val) in art.iter() {
+            assert_eq!(key / 2, val);
+        }
+
+        let mut iter = art.keys();
+        assert_eq!(Some(keys[0]), iter.next());
+        assert_eq!(Some(keys[keys.len() - 1]), iter.next_back());
+        assert_eq!(keys.len() - 2, iter.count());
+    }
+
+    #[test]
+    fn string_iteration() {
+        let mut art: ARTree<String, u32> = ARTree::new();
+        let mut names = vec!["Jerry", "Jenny", "Jenson", "Jen", "Je", "Wendell",
+                             "Anna", "Ann", "Annabel", "A", "Zane"];
+
+        for (i, name) in names.iter().enumerate() {
+            art.insert(name.to_string(), i as u32);
+        }
+
+        assert_eq!(7, *art.get("Ann").unwrap());
+        assert_eq!(9, *art.get("A").unwrap());
+
+        names.sort_unstable();
+        assert!(art.keys().eq(names.iter().map(|name| name.to_string())));
+        assert!(art.keys().rev().eq(names.iter().rev().map(|name| name.to_string())));
+    }
+
+    #[test]
+    fn range_queries() {
+        const SEED: u64 = 34;
+
+        let mut rng = Pcg64::seed_from_u64(SEED);
+        let mut art = ARTree::<u64, u64>::new();
+        let mut btree = BTreeMap::new();
+
+        for _ in 0..5_000 {
+            let key = rng.gen_range(0..100_000);
+            art.insert(key, key + 1);
+            btree.insert(key, key + 1);
+        }
+
+        for _ in 0..200 {
+            let a = rng.gen_range(0..110_000);
+            let b = rng.gen_range(a..110_000);
+
+            assert!(art.range(a..b).map(|(k, &v)| (k, v)).eq(btree.range(a..b).map(|(&k, &v)| (k, v))));
+            assert!(art.range(a..=b).rev().map(|(k, _)| k).eq(btree.range(a..=b).rev().map(|(&k, _)| k)));
+            assert!(art.range(..b).map(|(k, _)| k).eq(btree.range(..b).map(|(&k, _)| k)));
+            assert!(art.range((Bound::Excluded(a), Bound::Unbounded)).map(|(k, _)| k)
+                       .eq(btree.range((Bound::Excluded(a), Bound::Unbounded)).map(|(&k, _)| k)));
+        }
+
+        for (_, val) in art.range_mut(1_000..2_000) {
+            *val = 0;
+        }
+
+        for (&key, val) in btree.range_mut(1_000..2_000) {
+            assert_eq!(Some(&0), art.get(&key));
+            *val = 0;
+        }
+
+        assert!(art.values().eq(btree.values()));
+    }
+
+    #[test]
+    fn prefix_scan() {
+        let mut art: ARTree<String, u32> = ARTree::new();
+        let names = ["Jerry", "Jenny", "Jenson", "Jen", "Je", "Wendell",
+                     "Anna", "Ann", "Annabel", "A", "Zane", "Jo"];
+
+        for (i, name) in names.iter().enumerate() {
+            art.insert(name.to_string(), i as u32);
+        }
+
+        let scan = |prefix: &str| {
+            art.prefix_iter(prefix).map(|(key, _)| key).collect::<Vec<_>>()
+        };
+
+        assert_eq!(vec!["Je", "Jen", "Jenny", "Jenson", "Jerry"], scan("Je"));
+        assert_eq!(vec!["Jen", "Jenny", "Jenson"], scan("Jen"));
+        assert_eq!(vec!["Jenny"], scan("Jenn"));
+        assert_eq!(vec!["A", "Ann", "Anna", "Annabel"], scan("A"));
+        assert_eq!(vec!["Anna", "Annabel"], scan("Anna"));
+        assert_eq!(vec!["Zane"], scan("Zane"));
+        assert_eq!(names.len(), scan("").len());
+        assert!(scan("Jenz").is_empty());
+        assert!(scan("Zanes").is_empty());
+        assert!(scan("B").is_empty());
+    }
+
+    #[test]
+    fn signed_and_float_order() {
+        let ints = [i64::MIN, -1_000_000, -256, -1, 0, 1, 255, 256, i64::MAX];
+        let mut art = ARTree::<i64, usize>::new();
+
+        for (i, &key) in ints.iter().enumerate().rev() {
+            art.insert(key, i);
+        }
+
+        assert!(art.keys().eq(ints.iter().copied()));
+        assert!(art.values().copied().eq(0..ints.len()));
+        assert!(art.range(-256..=1).map(|(key, _)| key).eq([-256, -1, 0, 1]));
+
+        let floats = [f64::NEG_INFINITY, -1e300, -2.5, -1.0, -f64::MIN_POSITIVE, -0.0,
+                      0.0, f64::MIN_POSITIVE, 1.0, 2.5, 1e300, f64::INFINITY];
+        let mut art = ARTree::<f64, usize>::new();
+
+        for (i, &key) in floats.iter().enumerate().rev() {
+            art.insert(key, i);
+        }
+
+        assert!(art.values().copied().eq(0..floats.len()));
+        for (key, expected) in art.keys().zip(floats) {
+            assert_eq!(expected.to_bits(), key.to_bits());
+        }
+    }
+
+    #[test]
+    fn key_type_order() {
+        fn check_order<K: ARTKeyDecode + Ord + Clone + Debug>(mut keys: Vec<K>) {
+            let mut art = ARTree::<K, usize>::new();
+
+            for (i, key) in keys.iter().enumerate() {
+                art.insert(key.clone(), i);
             }
+
+            keys.sort();
+            assert_eq!(keys, art.keys().collect::<Vec<_>>());
+        }
+
+        check_order(vec![i128::MAX, -1, 0, i128::MIN, 1 << 100, -(1 << 100)]);
+        check_order(vec![u128::MAX, 0, 1, 1 << 64, 255]);
+        check_order(vec![i8::MIN, 0, -1, 1, i8::MAX]);
+        check_order(vec![255u8, 0, 17]);
+        check_order(vec!['z', 'a', '\u{10FFFF}', 'é', '\0']);
+        check_order(vec![true, false]);
+        check_order(vec![NonZeroI32::new(-5).unwrap(), NonZeroI32::new(3).unwrap(),
+                         NonZeroI32::new(i32::MIN).unwrap()]);
+        check_order(vec![vec![1u8, 2], vec![1], vec![], vec![0, 255], vec![1, 2, 3]]);
+        check_order(vec![[3u8, 1], [0, 9], [3, 0]]);
+
+        let mut art = ARTree::<&str, usize>::new();
+        art.insert("beta", 1);
+        art.insert("alpha", 0);
+        assert_eq!(Some(&0), art.get("alpha"));
+        assert_eq!(Some(&1), art.get(&"beta"));
+
+        let mut art = ARTree::<Vec<u8>, usize>::new();
+        art.insert(b"hash".to_vec(), 0);
+        assert_eq!(Some(&0), art.get(&b"hash"[..]));
+    }
+
+    #[test]
+    fn composite_keys() {
+        let mut art = ARTree::<(u32, String, i64), usize>::new();
+        let mut rows = vec![
+            (2, String::from("b"), -5),
+            (1, String::from("ab"), 7),
+            (1, String::from("a"), 100),
+            (1, String::from("a\0"), -1),
+            (1, String::from("a"), -100),
+            (2, String::new(), 0),
+            (10, String::from("a"), 0),
+            (1, String::from("b"), i64::MIN),
+        ];
+
+        for (i, row) in rows.iter().enumerate() {
+            art.insert(row.clone(), i);
+        }
+
+        rows.sort();
+        assert_eq!(rows, art.keys().collect::<Vec<_>>());
+
+        let tenant: Vec<_> = art.prefix_iter(&(1u32,)).map(|(key, _)| key).collect();
+        assert_eq!(rows[..5], tenant[..]);
+
+        let name: Vec<_> = art.prefix_iter(&(1u32, "a")).map(|(key, _)| key).collect();
+        assert_eq!(rows[..2], name[..]);
+    }
+
+    #[cfg(feature = "derive")]
+    #[test]
+    fn derived_keys() {
+        use crate::keys::{ARTKey, ARTKeyDecode};
+
+        #[derive(ARTKey, ARTKeyDecode, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+        struct Row {
+            tenant: u32,
+            name: String,
+            ts: i64,
+        }
+
+        #[derive(ARTKey, ARTKeyDecode, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+        struct Wrapper(Row, bool);
+
+        let mut keys: Vec<_> = [(3, "x", 1), (1, "y", -2), (1, "x", 5), (1, "x", -5)]
+            .into_iter()
+            .map(|(tenant, name, ts)| Wrapper(Row { tenant, name: name.to_string(), ts }, ts > 0))
+            .collect();
+
+        let mut art = ARTree::<Wrapper, ()>::new();
+        for key in keys.iter() {
+            art.insert(key.clone(), ());
+        }
+
+        keys.sort();
+        assert_eq!(keys, art.keys().collect::<Vec<_>>());
+        assert_eq!(3, art.prefix_iter(&(1u32,)).count());
+    }
+
+    #[test]
+    fn entry_api() {
+        const SEED: u64 = 5;
+
+        let mut rng = Pcg64::seed_from_u64(SEED);
+        let mut art = ARTree::<u64, u64>::new();
+        let mut btree = BTreeMap::new();
+
+        for _ in 0..20_000 {
+            let key = rng.gen_range(0..2_000) << rng.gen_range(0..40);
+            *art.entry(key).or_insert(0) += 1;
+            *btree.entry(key).or_insert(0) += 1;
+        }
+
+        assert!(art.iter().map(|(k, &v)| (k, v)).eq(btree.iter().map(|(&k, &v)| (k, v))));
+
+        let mut words = ARTree::<String, Vec<usize>>::new();
+        for (i, word) in ["an", "ant", "a", "and", "an", "a", ""].into_iter().enumerate() {
+            words.entry(word.to_string()).or_default().push(i);
+        }
+
+        assert_eq!(Some(&vec![0, 4]), words.get("an"));
+        assert_eq!(Some(&vec![2, 5]), words.get("a"));
+        assert_eq!(Some(&vec![6]), words.get(""));
+
+        words.entry(String::from("ant")).and_modify(|v| v.clear()).or_default().push(9);
+        words.entry(String::from("bee")).and_modify(|v| v.clear()).or_insert_with(|| vec![10]);
+        assert_eq!(Some(&vec![9]), words.get("ant"));
+        assert_eq!(Some(&vec![10]), words.get("bee"));
+
+        match words.entry(String::from("an")) {
+            Entry::Occupied(entry) => {
+                assert_eq!(&vec![0, 4], entry.get());
+                assert_eq!(vec![0, 4], entry.remove());
+            }
+            Entry::Vacant(_) => panic!("\"an\" should be present"),
+        }
+
+        let entry = words.entry(String::from("an")).insert(vec![1]);
+        assert_eq!("an", entry.key());
+        assert!(words.keys().eq(["", "a", "an", "and", "ant", "bee"].map(String::from)));
+    }
+
+    #[test]
+    fn len_and_in_place_updates() {
+        let mut art = ARTree::<String, u32>::new();
+        assert!(art.is_empty());
+
+        for name in ["Jen", "Jenny", "Jenson", "J", "Zane"] {
+            assert_eq!(None, art.insert(name.to_string(), 1));
+        }
+        assert_eq!(Some(1), art.insert(String::from("Jen"), 2));
+        assert_eq!(5, art.len());
+
+        *art.get_mut("Jenny").unwrap() += 10;
+        *art.get_mut("J").unwrap() += 20;
+        assert_eq!(None, art.get_mut("Je"));
+        assert_eq!(Some(&11), art.get("Jenny"));
+        assert_eq!(Some(&21), art.get("J"));
+
+        assert!(art.contains_key("Jenson"));
+        assert!(!art.contains_key("Jens"));
+
+        assert_eq!(Some(2), art.delete("Jen"));
+        assert_eq!(None, art.delete("Jen"));
+        assert_eq!(4, art.len());
+
+        if let Entry::Occupied(entry) = art.entry(String::from("Zane")) {
+            entry.remove();
+        }
+        art.entry(String::from("Anna")).or_insert(0);
+        assert_eq!(4, art.len());
+        assert_eq!(art.len(), art.iter().count());
+
+        art.clear();
+        assert!(art.is_empty());
+        assert_eq!(None, art.get("Jenny"));
+        assert_eq!(0, art.iter().count());
+    }
+
+    /// A key of up to `max_len - 1` bytes drawn from the first `alphabet`
+    /// byte values.
+    fn random_key(rng: &mut Pcg64, alphabet: usize, max_len: usize) -> Vec<u8> {
+        let len = rng.gen_range(0..max_len);
+        (0..len).map(|_| rng.gen_range(0..alphabet) as u8).collect()
+    }
+
+    fn assert_compact<V>(node: &ARTNode<V>, is_root: bool) {
+        if let ARTNode::Inner(inner, _, val) = node {
+            let children = inner.sorted_children();
+            let things = children.len() + val.is_some() as usize;
+
+            assert_eq!(children.len(), inner.children_num());
+            assert!(things >= 2 || (is_root && things >= 1), "uncompressed inner node");
+
+            for (_, child) in children {
+                assert_compact(child, false);
+            }
+        }
+    }
+
+    fn assert_smallest<V>(node: &ARTNode<V>) {
+        if let ARTNode::Inner(inner, _, _) = node {
+            let children = inner.children_num();
+            let fits = match inner {
+                ARTInnerNode::Inner4(_) => children <= 4,
+                ARTInnerNode::Inner16(_) => (5..=16).contains(&children),
+                ARTInnerNode::Inner48(_) => (17..=48).contains(&children),
+                ARTInnerNode::Inner256(_) => children > 48,
+            };
+            assert!(fits, "node built larger than needed");
+
+            for (_, child) in inner.sorted_children() {
+                assert_smallest(child);
+            }
+        }
+    }
+
+    fn assert_persistent_compact<V>(node: &PersistentNode<V>, is_root: bool) {
+        if let PersistentNode::Inner(inner, _, val) = node {
+            let children = inner.sorted_children();
+            let things = children.len() + val.is_some() as usize;
+            let fits = match inner {
+                ARTInnerNode::Inner4(_) => children.len() <= 4,
+                ARTInnerNode::Inner16(_) => (5..=16).contains(&children.len()),
+                ARTInnerNode::Inner48(_) => (17..=48).contains(&children.len()),
+                ARTInnerNode::Inner256(_) => children.len() > 48,
+            };
+
+            assert_eq!(children.len(), inner.children_num());
+            assert!(things >= 2 || (is_root && things >= 1), "uncompressed inner node");
+            assert!(fits, "node kept larger than needed");
+
+            for (_, child) in children {
+                assert_persistent_compact(child, false);
+            }
+        }
+    }
+
+    #[test]
+    fn delete_collapses_nodes() {
+        const SEED: u64 = 8;
+
+        let mut rng = Pcg64::seed_from_u64(SEED);
+        let mut art = ARTree::<Vec<u8>, usize>::new();
+        let mut keys = Vec::new();
+
+        for i in 0..20_000 {
+            let len = rng.gen_range(0..6);
+            let key: Vec<u8> = (0..len).map(|_| rng.gen_range(0..4)).collect();
+            art.insert(key.clone(), i);
+            keys.push(key);
+        }
+
+        keys.sort_unstable();
+        keys.dedup();
+
+        for key in keys.iter().skip(1).step_by(3) {
+            assert!(art.delete(key).is_some());
+        }
+
+        assert_compact(art.root.as_ref().unwrap(), true);
+        assert_eq!(keys.len() - keys.iter().skip(1).step_by(3).count(), art.len());
+
+        for key in keys.iter() {
+            art.delete(key);
+        }
+
+        assert!(art.root.is_none());
+        assert!(art.is_empty());
+
+        let mut art = ARTree::<String, u32>::new();
+        art.insert(String::from("abcdef"), 1);
+        art.insert(String::from("abcxyz"), 2);
+        art.insert(String::from("abc"), 3);
+        art.delete("abcxyz");
+        art.delete("abc");
+
+        match art.root.as_ref() {
+            Some(ARTNode::Leaf(leaf)) => assert_eq!(b"abcdef", leaf.pkey()),
+            _ => panic!("single entry should be stored as a leaf"),
+        }
+    }
+
+    #[test]
+    fn delete_structural_cases() {
+        let mut art = ARTree::<String, u32>::new();
+
+        // root leaf
+        art.insert(String::from("solo"), 1);
+        assert_eq!(None, art.delete("sol"));
+        assert_eq!(None, art.delete("solos"));
+        assert_eq!(Some(1), art.delete("solo"));
+        assert!(art.root.is_none());
+
+        // value on an inner node with an inner child below it
+        for (i, key) in ["ab", "abc", "abcd", "abce", "abd"].into_iter().enumerate() {
+            art.insert(key.to_string(), i as u32);
+        }
+
+        // a key ending inside a compressed prefix is not a match
+        assert_eq!(None, art.delete("a"));
+        assert_eq!(None, art.delete(""));
+
+        assert_eq!(Some(1), art.delete("abc"));
+        assert!(art.keys().eq(["ab", "abcd", "abce", "abd"].map(String::from)));
+        assert_eq!(Some(0), art.delete("ab"));
+        assert!(art.keys().eq(["abcd", "abce", "abd"].map(String::from)));
+        assert_eq!(Some(4), art.delete("abd"));
+        assert_eq!(Some(2), art.delete("abcd"));
+        assert!(art.keys().eq(["abce"].map(String::from)));
+        assert_eq!(Some(&3), art.get("abce"));
+        assert_compact(art.root.as_ref().unwrap(), true);
+    }
+
+    #[test]
+    fn model_against_btreemap() {
+        const SEED: u64 = 77;
+
+        let mut rng = Pcg64::seed_from_u64(SEED);
+
+        // narrow alphabets give many prefix keys, wide ones grow and shrink
+        // nodes through every size
+        for (alphabet, max_len) in [(3, 5), (256, 3)] {
+            let mut art = ARTree::<Vec<u8>, u32>::new();
+            let mut model = BTreeMap::new();
+
+            for round in 0..40_000 {
+                let key = random_key(&mut rng, alphabet, max_len);
+
+                match rng.gen_range(0..10) {
+                    0..=3 => assert_eq!(model.insert(key.clone(), round), art.insert(key, round)),
+                    4..=6 => assert_eq!(model.remove(&key), art.delete(&key)),
+                    7 => {
+                        if let Entry::Occupied(entry) = art.entry(key.clone()) {
+                            assert_eq!(model.remove(&key), Some(entry.remove()));
+                        }
+                    }
+                    _ => assert_eq!(model.get(&key), art.get(&key)),
+                }
+
+                assert_eq!(model.len(), art.len());
+
+                if round % 500 == 0 {
+                    assert!(art.iter().map(|(k, &v)| (k, v))
+                               .eq(model.iter().map(|(k, &v)| (k.clone(), v))));
+                    if let Some(root) = art.root.as_ref() {
+                        assert_compact(root, true);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn std_traits() {
+        let pairs = [(300u32, "c"), (7, "a"), (70_000, "d"), (8, "b")];
+        let mut art: ARTree<u32, &str> = pairs.into_iter().collect();
+        let model: BTreeMap<u32, &str> = pairs.into_iter().collect();
+
+        assert_eq!(4, art.len());
+        assert_eq!("c", art[&300]);
+        assert_eq!(format!("{:?}", model), format!("{:?}", art));
+
+        let copy = art.clone();
+        assert_eq!(copy, art);
+
+        art.extend([(9, "x"), (7, "y")]);
+        assert_ne!(copy, art);
+        art.extend(copy.iter().map(|(key, _)| (key, "z")));
+        assert_eq!(5, art.len());
+        assert_eq!("z", art[&7]);
+        assert_eq!("x", art[&9]);
+
+        for (_, value) in &mut art {
+            *value = "w";
+        }
+        assert!((&art).into_iter().all(|(_, &value)| value == "w"));
+
+        let entries: Vec<_> = art.into_iter().rev().collect();
+        assert_eq!(vec![(70_000, "w"), (300, "w"), (9, "w"), (8, "w"), (7, "w")], entries);
+
+        let hash = |tree: &ARTree<u32, &str>| {
+            let mut hasher = DefaultHasher::new();
+            tree.hash(&mut hasher);
+            hasher.finish()
+        };
+        let reordered: ARTree<u32, &str> = pairs.into_iter().rev().collect();
+        assert_eq!(copy, reordered);
+        assert_eq!(hash(&copy), hash(&reordered));
+
+        // owned iteration drops whatever it did not yield
+        let strings: ARTree<String, String> = (0..300)
+            .map(|i| (format!("key{}", i), i.to_string()))
+            .collect();
+        let mut iter = strings.into_iter();
+        assert_eq!(Some((String::from("key0"), String::from("0"))), iter.next());
+        assert_eq!(Some((String::from("key99"), String::from("99"))), iter.next_back());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        const SEED: u64 = 15;
+
+        let mut rng = Pcg64::seed_from_u64(SEED);
+
+        for (alphabet, max_len) in [(3, 6), (256, 3)] {
+            let mut model = BTreeMap::new();
+            let mut inserted = ARTree::<String, u32>::new();
+
+            for i in 0..5_000 {
+                let key: String = random_key(&mut rng, alphabet, max_len).into_iter()
+                                                                          .map(char::from)
+                                                                          .collect();
+                model.insert(key.clone(), i);
+                inserted.insert(key, i);
+            }
+
+            // the model writes keys in order, so this takes the bulk path
+            let json = serde_json::to_string(&model).unwrap();
+            let art: ARTree<String, u32> = serde_json::from_str(&json).unwrap();
+
+            assert_compact(art.root.as_ref().unwrap(), true);
+            assert_eq!(inserted, art);
+            assert_eq!(json, serde_json::to_string(&art).unwrap());
+        }
+
+        let numbers: ARTree<u64, bool> = (0..1_000).map(|i| (i * 7919, i % 2 == 0)).collect();
+        let json = serde_json::to_string(&numbers).unwrap();
+        assert_eq!(numbers, serde_json::from_str(&json).unwrap());
+
+        let empty: ARTree<String, u32> = serde_json::from_str("{}").unwrap();
+        assert!(empty.is_empty());
+
+        let unsorted: ARTree<String, u32> =
+            serde_json::from_str(r#"{"b": 1, "bc": 2, "a": 3, "bc": 4}"#).unwrap();
+        assert_eq!(3, unsorted.len());
+        assert_eq!(Some(&3), unsorted.get("a"));
+        assert_eq!(Some(&4), unsorted.get("bc"));
+    }
+
+    #[test]
+    fn frozen_snapshot() {
+        const SEED: u64 = 16;
+
+        let mut rng = Pcg64::seed_from_u64(SEED);
+
+        // narrow alphabets give deep trees of Node4s, wide ones every node size
+        for (alphabet, max_len) in [(3, 8), (256, 3)] {
+            let mut art = ARTree::<Vec<u8>, u64>::new();
+            let mut absent = Vec::new();
+
+            for i in 0..20_000 {
+                let key = random_key(&mut rng, alphabet, max_len);
+                if i % 4 == 0 {
+                    absent.push(key);
+                } else {
+                    art.insert(key, i);
+                }
+            }
+
+            let mut image = Vec::new();
+            art.write_snapshot(&mut image).unwrap();
+            let frozen = FrozenARTree::<Vec<u8>, u64, _>::from_bytes(image).unwrap();
+
+            assert_eq!(art.len(), frozen.len());
+            for (key, &value) in art.iter() {
+                assert_eq!(Some(value), frozen.get(&key));
+            }
+            for key in absent.iter().filter(|&key| !art.contains_key(key)) {
+                assert_eq!(None, frozen.get(key));
+            }
+
+            assert!(frozen.iter().eq(art.iter().map(|(k, &v)| (k, v))));
+            assert!(frozen.iter().rev().eq(art.iter().rev().map(|(k, &v)| (k, v))));
+
+            for prefix in [vec![], vec![1], vec![2, 0], vec![1, 2, 1], vec![200, 3]] {
+                assert!(frozen.prefix_iter(&prefix)
+                              .eq(art.prefix_iter(&prefix).map(|(k, &v)| (k, v))));
+            }
+
+            let (low, high) = (vec![1, 0], vec![2, 1, 1]);
+            assert!(frozen.range(low.clone()..=high.clone())
+                          .eq(art.range(low.clone()..=high.clone()).map(|(k, &v)| (k, v))));
+            assert!(frozen.range(..high.clone()).rev()
+                          .eq(art.range(..high).rev().map(|(k, &v)| (k, v))));
+        }
+
+        let names: ARTree<String, String> = ["Jen", "Jenny", "Jenson", "J", "Zane"]
+            .into_iter()
+            .map(|name| (name.to_string(), name.to_lowercase()))
+            .collect();
+        let path = std::env::temp_dir().join(format!("rust_art_{}.snapshot", std::process::id()));
+        names.write_snapshot(std::fs::File::create(&path).unwrap()).unwrap();
+
+        let frozen = FrozenARTree::<String, String>::open(&path).unwrap();
+        assert_eq!(Some("jenny"), frozen.get("Jenny"));
+        assert_eq!(None, frozen.get("Je"));
+        assert_eq!(vec!["Jen", "Jenny", "Jenson"],
+                   frozen.prefix_iter("Jen").map(|(key, _)| key).collect::<Vec<_>>());
+        drop(frozen);
+        std::fs::remove_file(&path).unwrap();
+
+        let mut image = Vec::new();
+        names.write_snapshot(&mut image).unwrap();
+
+        let mut corrupted = image.clone();
+        corrupted[20] ^= 1;
+        assert!(matches!(FrozenARTree::<String, String, _>::from_bytes(corrupted),
+                         Err(SnapshotError::ChecksumMismatch)));
+        assert!(matches!(FrozenARTree::<String, String, _>::from_bytes(&image[..30]),
+                         Err(SnapshotError::Truncated)));
+        assert!(matches!(FrozenARTree::<String, String, _>::from_bytes(&image[1..]),
+                         Err(SnapshotError::NotASnapshot)));
+
+        // unverified images with damaged nodes must still read without panicking
+        let mut art = ARTree::<Vec<u8>, Vec<u8>>::new();
+        for _ in 0..300 {
+            let key = random_key(&mut rng, 256, 3);
+            art.insert(key.clone(), key);
+        }
+        let mut image = Vec::new();
+        art.write_snapshot(&mut image).unwrap();
+
+        for _ in 0..500 {
+            let mut corrupted = image.clone();
+            for _ in 0..rng.gen_range(1..5) {
+                let at = rng.gen_range(16..image.len() - 20);
+                corrupted[at] = rng.gen();
+            }
+            std::fs::write(&path, &corrupted).unwrap();
+
+            let frozen = FrozenARTree::<Vec<u8>, Vec<u8>>::open_unverified(&path).unwrap();
+            for (key, _) in art.iter().step_by(10) {
+                frozen.get(&key);
+            }
+            frozen.iter().rev().count();
+            frozen.prefix_iter(&[7]).count();
+            frozen.range(vec![100]..vec![200, 1]).count();
+        }
+        std::fs::remove_file(&path).unwrap();
+
+        let mut empty = Vec::new();
+        ARTree::<String, String>::new().write_snapshot(&mut empty).unwrap();
+        let frozen = FrozenARTree::<String, String, _>::from_bytes(empty).unwrap();
+        assert!(frozen.is_empty());
+        assert_eq!(None, frozen.iter().next());
+    }
+
+    #[test]
+    fn sorted_bulk_load() {
+        const SEED: u64 = 17;
+
+        let mut rng = Pcg64::seed_from_u64(SEED);
+
+        for (alphabet, max_len) in [(3, 8), (256, 3)] {
+            let mut model = BTreeMap::new();
+            for i in 0..20_000 {
+                let key = random_key(&mut rng, alphabet, max_len);
+                model.insert(key, i);
+            }
+
+            let art = ARTree::from_sorted_iter(model.clone()).unwrap();
+            let inserted: ARTree<Vec<u8>, u32> = model.clone().into_iter().collect();
+
+            assert_compact(art.root.as_ref().unwrap(), true);
+            assert_smallest(art.root.as_ref().unwrap());
+            assert_eq!(inserted, art);
+
+            // merging keeps every old entry and lets new values win
+            let mut merged: ARTree<Vec<u8>, u32> =
+                model.iter().step_by(3).map(|(key, &value)| (key.clone(), value)).collect();
+            let update: Vec<_> = model.iter().skip(1).step_by(2)
+                                      .map(|(key, &value)| (key.clone(), value + 1))
+                                      .collect();
+            merged.bulk_load(update.clone()).unwrap();
+
+            let mut expected: BTreeMap<_, _> = model.iter().step_by(3)
+                                                    .map(|(key, &value)| (key.clone(), value))
+                                                    .collect();
+            expected.extend(update);
+            assert_compact(merged.root.as_ref().unwrap(), true);
+            assert!(merged.iter().map(|(k, &v)| (k, v)).eq(expected.into_iter()));
+        }
+
+        assert_eq!(Err(BulkLoadError::Unsorted { index: 2 }),
+                   ARTree::from_sorted_iter([(1u32, ()), (5, ()), (3, ())]));
+        assert_eq!(Err(BulkLoadError::Duplicate { index: 1 }),
+                   ARTree::from_sorted_iter([("a".to_string(), 1), ("a".to_string(), 2)]));
+
+        let mut art: ARTree<u32, u32> = [(2, 0), (4, 0), (8, 0)].into_iter().collect();
+        assert_eq!(Err(BulkLoadError::Unsorted { index: 2 }),
+                   art.bulk_load([(3, 1), (4, 1), (1, 1), (9, 1)]));
+        assert_eq!(vec![(2, 0), (3, 1), (4, 1), (8, 0)],
+                   art.iter().map(|(k, &v)| (k, v)).collect::<Vec<_>>());
+        assert_eq!(4, art.len());
+
+        assert!(ARTree::<u32, u32>::from_sorted_iter([]).unwrap().is_empty());
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn parallel_sorted_build() {
+        const SEED: u64 = 18;
+
+        let mut rng = Pcg64::seed_from_u64(SEED);
+
+        // random keys split on the first byte, small ones below a shared prefix
+        let random: BTreeMap<u64, u64> = (0..50_000).map(|_| (rng.gen(), rng.gen())).collect();
+        let small: BTreeMap<u64, u64> = (0..50_000).map(|_| (rng.gen_range(0..1 << 20), 0)).collect();
+        let mut names: BTreeMap<String, u64> = (0..5_000)
+            .map(|i| (format!("user/{}", rng.gen_range(0..i + 1)), i))
+            .collect();
+        names.insert(String::from("user/"), 0);
+
+        for model in [random, small] {
+            let art = ARTree::par_from_sorted(model.clone()).unwrap();
+            assert_compact(art.root.as_ref().unwrap(), true);
+            assert_smallest(art.root.as_ref().unwrap());
+            assert_eq!(ARTree::from_sorted_iter(model).unwrap(), art);
+        }
+
+        let art = ARTree::par_from_sorted(names.clone()).unwrap();
+        assert_compact(art.root.as_ref().unwrap(), true);
+        assert_eq!(ARTree::from_sorted_iter(names).unwrap(), art);
+
+        let single = ARTree::par_from_sorted([(7u32, 'x')]).unwrap();
+        assert_eq!(vec![(7, 'x')], single.iter().map(|(k, &v)| (k, v)).collect::<Vec<_>>());
+        assert!(ARTree::<u32, u32>::par_from_sorted([]).unwrap().is_empty());
+
+        assert_eq!(Err(BulkLoadError::Unsorted { index: 3 }),
+                   ARTree::par_from_sorted([(1u32, ()), (2, ()), (300, ()), (4, ())]));
+        assert_eq!(Err(BulkLoadError::Duplicate { index: 1 }),
+                   ARTree::par_from_sorted([(1u32, ()), (1, ())]));
+    }
+
+    #[test]
+    fn transactions() {
+        const SEED: u64 = 29;
+
+        let mut rng = Pcg64::seed_from_u64(SEED);
+        let mut art: ARTree<Vec<u8>, u32> = (0..100u32).map(|i| (i.to_be_bytes()[2..].to_vec(), i)).collect();
+        let mut model: BTreeMap<Vec<u8>, u32> = art.iter().map(|(k, &v)| (k, v)).collect();
+
+        for round in 0..200 {
+            let mut tx = art.transaction();
+            let mut staged = model.clone();
+
+            for _ in 0..rng.gen_range(0..20) {
+                let key = (rng.gen_range(0..150u32)).to_be_bytes()[2..].to_vec();
+                match rng.gen_range(0..3) {
+                    0 => {
+                        tx.insert(key.clone(), round);
+                        staged.insert(key, round);
+                    }
+                    1 => {
+                        tx.delete(&key);
+                        staged.remove(&key);
+                    }
+                    // reads see the transaction's own writes
+                    _ => assert_eq!(staged.get(&key), tx.get(&key)),
+                }
+            }
+
+            if rng.gen_bool(0.5) {
+                tx.commit();
+                model = staged;
+            } else if rng.gen_bool(0.5) {
+                tx.rollback();
+            }
+
+            assert_eq!(model.len(), art.len());
+            assert!(art.iter().map(|(k, &v)| (k, v)).eq(model.clone()));
+        }
+    }
+
+    #[test]
+    fn durable_log() {
+        const SEED: u64 = 23;
+
+        let mut rng = Pcg64::seed_from_u64(SEED);
+        let dir = std::env::temp_dir().join(format!("rust_art_{}.durable", std::process::id()));
+        let log = dir.join("wal");
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let mut model = BTreeMap::new();
+        let mut write = |art: &mut DurableARTree<u32, String>, model: &mut BTreeMap<u32, String>| {
+            for _ in 0..500 {
+                let key = rng.gen_range(0..300);
+                if rng.gen_bool(0.7) {
+                    let value = format!("v{}", rng.gen::<u16>());
+                    assert_eq!(model.insert(key, value.clone()), art.insert(key, value).unwrap());
+                } else {
+                    assert_eq!(model.remove(&key), art.delete(&key).unwrap());
+                }
+            }
+        };
+        let reopen = |policy| DurableARTree::<u32, String>::open(&dir, policy).unwrap();
+        let matches = |art: &DurableARTree<u32, String>, model: &BTreeMap<u32, String>| {
+            art.len() == model.len() && art.tree().iter().eq(model.iter().map(|(&k, v)| (k, v)))
+        };
+
+        // a log header cut short before anything was logged is rewritten
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(&log, b"RARTWAL\0\x01\0").unwrap();
+        assert!(reopen(SyncPolicy::Always).is_empty());
+        assert_eq!(16, std::fs::metadata(&log).unwrap().len());
+
+        // the log alone, then a snapshot plus the log written after it
+        let mut art = reopen(SyncPolicy::Always);
+        write(&mut art, &mut model);
+        drop(art);
+
+        let mut art = reopen(SyncPolicy::Always);
+        assert!(matches(&art, &model));
+        art.checkpoint().unwrap();
+        assert_eq!(16, std::fs::metadata(&log).unwrap().len());
+        write(&mut art, &mut model);
+        drop(art);
+
+        let mut art = reopen(SyncPolicy::Always);
+        assert!(matches(&art, &model));
+
+        // a torn last record is dropped, and writes after it survive
+        art.insert(1_000, "torn".to_string()).unwrap();
+        drop(art);
+        let len = std::fs::metadata(&log).unwrap().len();
+        std::fs::OpenOptions::new().write(true).open(&log).unwrap().set_len(len - 3).unwrap();
+
+        let mut art = reopen(SyncPolicy::Always);
+        assert!(matches(&art, &model));
+        art.insert(1_001, "after".to_string()).unwrap();
+        model.insert(1_001, "after".to_string());
+        drop(art);
+
+        // unsynced writes of a batch are lost in a crash, synced ones are not
+        let mut art = reopen(SyncPolicy::Batch(10));
+        assert!(matches(&art, &model));
+        for key in 2_000..2_025 {
+            art.insert(key, key.to_string()).unwrap();
+            if key < 2_020 {
+                model.insert(key, key.to_string());
+            }
+        }
+        std::mem::forget(art);
+
+        let art = reopen(SyncPolicy::Manual);
+        assert!(matches(&art, &model));
+        drop(art);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn persistent_snapshots() {
+        const SEED: u64 = 79;
+
+        let mut rng = Pcg64::seed_from_u64(SEED);
+
+        for (alphabet, max_len) in [(3, 5), (256, 3)] {
+            let mut art = PersistentARTree::<Vec<u8>, u32>::new();
+            let mut model = BTreeMap::new();
+            let mut snapshots = Vec::new();
+
+            for round in 0..20_000 {
+                let key = random_key(&mut rng, alphabet, max_len);
+
+                match rng.gen_range(0..10) {
+                    0..=4 => assert_eq!(model.insert(key.clone(), round), art.insert(key, round)),
+                    5..=7 => assert_eq!(model.remove(&key), art.delete(&key)),
+                    _ => assert_eq!(model.get(&key), art.get(&key)),
+                }
+
+                assert_eq!(model.len(), art.len());
+
+                if round % 1_000 == 0 {
+                    snapshots.push((art.snapshot(), model.clone()));
+                }
+            }
+
+            // deletes leave the live tree as compact as a fresh one
+            for key in model.keys().cloned().collect::<Vec<_>>() {
+                if rng.gen_bool(0.9) {
+                    model.remove(&key);
+                    art.delete(&key);
+                }
+            }
+            assert_eq!(model.len(), art.len());
+            match art.root.as_deref() {
+                Some(root) => assert_persistent_compact(root, true),
+                None => assert!(model.is_empty()),
+            }
+
+            // every version still reads as it was when it was taken
+            snapshots.push((art, model));
+            for (snapshot, model) in snapshots.iter() {
+                assert_eq!(model.len(), snapshot.len());
+                assert!(snapshot.iter().map(|(k, &v)| (k, v))
+                                .eq(model.iter().map(|(k, &v)| (k.clone(), v))));
+                assert!(model.keys().all(|key| snapshot.get(key) == model.get(key)));
+            }
+        }
+
+        // a Node256 shrinks all the way down as its leaves are deleted
+        let mut art: PersistentARTree<Vec<u8>, u8> = (0..=255).map(|b| (vec![b, 7], b)).collect();
+        for b in 0..255 {
+            assert_eq!(Some(b), art.delete(&vec![b, 7]));
+            assert_persistent_compact(art.root.as_deref().unwrap(), true);
+        }
+        assert!(matches!(art.root.as_deref(), Some(PersistentNode::Leaf(_))));
+        assert_eq!(Some(255), art.delete(&vec![255, 7]));
+        assert!(art.root.is_none());
+
+        let mut art: PersistentARTree<u32, String> = (0..100).map(|i| (i, i.to_string())).collect();
+        let snapshot = art.snapshot();
+
+        assert_eq!(Some("7".to_string()), art.delete(&7));
+        assert_eq!(Some("8".to_string()), art.insert(8, "eight".to_string()));
+        assert_eq!(Some(&"7".to_string()), snapshot.get(&7));
+        assert_eq!(Some(&"8".to_string()), snapshot.get(&8));
+        assert_eq!(Some(&"eight".to_string()), art.get(&8));
+        assert!(snapshot.range(5..10).map(|(k, _)| k).eq(5..10));
+        assert!(art.range(5..10).map(|(k, _)| k).eq([5, 6, 8, 9]));
+
+        // dropping the tree keeps the snapshot intact
+        drop(art);
+        assert_eq!(100, snapshot.len());
+        assert!(snapshot.iter().map(|(k, v)| (k, v.clone())).eq((0..100).map(|i| (i, i.to_string()))));
+    }
+
+    #[test]
+    fn mvcc_versions() {
+        const SEED: u64 = 83;
+
+        let mut rng = Pcg64::seed_from_u64(SEED);
+        let mut art = MvccARTree::<u16, u32>::new();
+        // every write as (key, ts, value), in commit order
+        let mut log: Vec<(u16, u64, Option<u32>)> = Vec::new();
+
+        let model_at = |log: &[(u16, u64, Option<u32>)], ts: u64| {
+            let mut model = BTreeMap::new();
+            for &(key, _, value) in log.iter().take_while(|&&(_, version, _)| version <= ts) {
+                model.insert(key, value);
+            }
+            model.into_iter().filter_map(|(key, value)| Some((key, value?))).collect::<Vec<_>>()
+        };
+
+        for ts in 1..=3_000 {
+            let key = rng.gen_range(0..200);
+            if rng.gen_range(0..4) == 0 {
+                art.delete(key, ts);
+                log.push((key, ts, None));
+            } else {
+                art.insert(key, ts as u32, ts);
+                log.push((key, ts, Some(ts as u32)));
+            }
+        }
+
+        for ts in [0, 1, 500, 1_234, 2_999, 3_000, u64::MAX] {
+            let model = model_at(&log, ts);
+            assert!(art.range_at(.., ts).map(|(k, &v)| (k, v)).eq(model.iter().copied()));
+            assert!(art.range_at(50..150, ts).rev().map(|(k, &v)| (k, v))
+                       .eq(model.iter().copied().filter(|(k, _)| (50..150).contains(k)).rev()));
+            assert!((0..200).all(|key| {
+                art.get_at(&key, ts).copied() == model.iter().find(|&&(k, _)| k == key).map(|&(_, v)| v)
+            }));
+        }
+
+        // readers at 2_000 and later see the same data after collecting
+        let before: Vec<_> = (2_000..=3_000).step_by(100).map(|ts| model_at(&log, ts)).collect();
+        let versions: usize = (0..200).filter_map(|key| art.versions(&key)).map(|chain| chain.len()).sum();
+        let removed = art.gc(2_000);
+        let after: usize = (0..200).filter_map(|key| art.versions(&key)).map(|chain| chain.len()).sum();
+
+        assert_eq!(versions - removed, after);
+        assert!(removed > 0);
+        for (ts, model) in (2_000..=3_000).step_by(100).zip(before) {
+            assert!(art.range_at(.., ts).map(|(k, &v)| (k, v)).eq(model));
+        }
+
+        // at most the one version visible at the horizon is older than it,
+        // and never a tombstone
+        for key in 0..200 {
+            if let Some(chain) = art.versions(&key) {
+                let old: Vec<_> = chain.iter().filter(|&(ts, _)| ts <= 2_000).collect();
+                assert!(old.len() <= 1 && old.iter().all(|(_, value)| value.is_some()));
+                assert!(!chain.is_empty());
+            }
+        }
+        assert_eq!(0, art.gc(2_000));
+
+        art.delete(7, 4_000);
+        art.gc(u64::MAX);
+        assert!(art.versions(&7).is_none());
+    }
+
+    #[cfg(not(loom))]
+    #[test]
+    fn concurrent_tree() {
+        const SEED: u64 = 91;
+        const THREADS: u8 = 4;
+
+        let rounds = if cfg!(miri) { 200 } else { 20_000 };
+        let art = ConcurrentARTree::<Vec<u8>, u32>::new();
+
+        // every thread owns the keys starting with its id, so it can check
+        // its own model exactly while the others reshape shared nodes; the
+        // narrow alphabet keeps splitting, growing and merging them
+        let lens: Vec<usize> = std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..THREADS).map(|id| {
+                let art = &art;
+                scope.spawn(move || {
+                    let mut rng = Pcg64::seed_from_u64(SEED + id as u64);
+                    let mut model = BTreeMap::new();
+
+                    for round in 0..rounds {
+                        let len = rng.gen_range(0..5);
+                        let mut key = vec![id % 2, id];
+                        key.extend((0..len).map(|_| rng.gen_range(0..3) as u8));
+
+                        match rng.gen_range(0..10) {
+                            0..=3 => assert_eq!(model.insert(key.clone(), round), art.insert(key, round)),
+                            4..=6 => assert_eq!(model.remove(&key), art.delete(&key)),
+                            _ => assert_eq!(model.get(&key).copied(), art.get(&key)),
+                        }
+                    }
+
+                    for (key, &value) in model.iter() {
+                        assert_eq!(Some(value), art.get(key));
+                    }
+                    model.len()
+                })
+            }).collect();
+
+            workers.into_iter().map(|worker| worker.join().unwrap()).collect()
+        });
+
+        assert_eq!(lens.iter().sum::<usize>(), art.len());
+        assert_eq!(None, art.get(&vec![0, 1]));
+
+        // growing past every node size and shrinking back
+        let art = ConcurrentARTree::<u32, u32>::new();
+        std::thread::scope(|scope| {
+            for id in 0..THREADS as u32 {
+                let art = &art;
+                scope.spawn(move || {
+                    for key in (id..1_000).step_by(THREADS as usize) {
+                        assert_eq!(None, art.insert(key, key));
+                    }
+                    for key in (id..1_000).step_by(THREADS as usize).filter(|key| key % 3 != 0) {
+                        assert_eq!(Some(key), art.delete(&key));
+                    }
+                });
+            }
+        });
+
+        assert_eq!(334, art.len());
+        assert!((0..1_000).all(|key| art.get(&key) == (key % 3 == 0).then_some(key)));
+    }
+
+    #[cfg(not(loom))]
+    #[test]
+    fn rowex_tree() {
+        const SEED: u64 = 93;
+
+        let rounds = if cfg!(miri) { 200 } else { 20_000 };
+        let art = RowexARTree::<Vec<u8>, u32>::new();
+
+        // the stable keys sit next to ones the writers keep adding and
+        // removing, so the nodes holding them are replaced under the readers
+        let stable: Vec<Vec<u8>> = (0..3u8).flat_map(|a| (0..3u8).map(move |b| vec![a, b])).collect();
+        for (value, key) in stable.iter().enumerate() {
+            art.insert(key.clone(), value as u32);
+        }
+
+        std::thread::scope(|scope| {
+            for id in 0..2u64 {
+                let art = &art;
+                scope.spawn(move || {
+                    let mut rng = Pcg64::seed_from_u64(SEED + id);
+                    for round in 0..rounds {
+                        let len = rng.gen_range(0..4);
+                        let mut key: Vec<u8> = (0..len).map(|_| rng.gen_range(0..3) as u8).collect();
+                        // keeps writers away from the stable keys
+                        key.push(3 + id as u8);
+
+                        if rng.gen_bool(0.5) {
+                            art.insert(key, round);
+                        } else {
+                            art.delete(&key);
+                        }
+                    }
+                });
+            }
+
+            for _ in 0..2 {
+                let (art, stable) = (&art, &stable);
+                scope.spawn(move || {
+                    for _ in 0..rounds / 10 {
+                        for (value, key) in stable.iter().enumerate() {
+                            assert_eq!(Some(value as u32), art.get(key));
+                        }
+                    }
+                });
+            }
+        });
+
+        for (value, key) in stable.iter().enumerate() {
+            assert_eq!(Some(value as u32), art.delete(key));
+        }
+        assert_eq!(None, art.get(&vec![0, 0]));
+    }
+
+    /// Runs `write` against `keys` while a ROWEX reader looks up `read`,
+    /// which must be found in every interleaving.
+    #[cfg(loom)]
+    fn loom_rowex(keys: &'static [&'static [u8]],
+                  read: &'static [u8],
+                  write: fn(&RowexARTree<Vec<u8>, u8>)) {
+        use loom::sync::Arc;
+
+        loom::model(move || {
+            let art = Arc::new(RowexARTree::<Vec<u8>, u8>::new());
+            for (value, key) in keys.iter().enumerate() {
+                art.insert(key.to_vec(), value as u8);
+            }
+            let expected = keys.iter().position(|&key| key == read).map(|value| value as u8);
+
+            let writer = {
+                let art = art.clone();
+                loom::thread::spawn(move || write(&art))
+            };
+
+            assert_eq!(expected, art.get(read));
+            writer.join().unwrap();
+            assert_eq!(expected, art.get(read));
+        });
+    }
+
+    #[cfg(loom)]
+    #[test]
+    fn loom_rowex_grow() {
+        loom_rowex(&[&[1, 2, 3, 0], &[1, 2, 3, 1], &[1, 2, 3, 2], &[1, 2, 3, 3]], &[1, 2, 3, 1],
+                   |art| assert_eq!(None, art.insert(vec![1, 2, 3, 4], 9)));
+    }
+
+    #[cfg(loom)]
+    #[test]
+    fn loom_rowex_prefix_split() {
+        loom_rowex(&[&[1, 2, 3, 0], &[1, 2, 3, 1]], &[1, 2, 3, 1],
+                   |art| assert_eq!(None, art.insert(vec![1, 2], 9)));
+    }
+
+    #[cfg(loom)]
+    #[test]
+    fn loom_rowex_merge() {
+        loom_rowex(&[&[1, 2, 3, 5, 0], &[1, 2, 3, 5, 1], &[1, 2, 4]], &[1, 2, 3, 5, 1],
+                   |art| assert_eq!(Some(2), art.delete(&vec![1, 2, 4])));
+    }
+
+    #[cfg(loom)]
+    #[test]
+    fn loom_concurrent_writers() {
+        use loom::sync::Arc;
+
+        // restarts loop, so the writers' search is bounded by preemptions
+        let mut model = loom::model::Builder::new();
+        model.preemption_bound = Some(3);
+
+        model.check(|| {
+            let art = Arc::new(ConcurrentARTree::<Vec<u8>, u8>::new());
+            for key_byte in 0..2 {
+                art.insert(vec![1, 2, key_byte], key_byte);
+            }
+
+            // the insert adds a child to the node that the delete collapses
+            // into its remaining leaf
+            let writer = {
+                let art = art.clone();
+                loom::thread::spawn(move || assert_eq!(None, art.insert(vec![1, 2, 2], 2)))
+            };
+            assert_eq!(Some(0), art.delete(&vec![1, 2, 0]));
+            writer.join().unwrap();
+
+            assert_eq!(2, art.len());
+            assert!((1..3).all(|key_byte| art.get(&vec![1, 2, key_byte]) == Some(key_byte)));
+        });
+    }
+
+    #[test]
+    fn art_set() {
+        const SEED: u64 = 31;
+
+        let mut rng = Pcg64::seed_from_u64(SEED);
+
+        // short keys over a small alphabet make many keys prefixes of others,
+        // and sparse long ones give compressed prefixes that part at any depth
+        for (alphabet, max_len, count) in [(4, 4, 400), (2, 12, 60), (256, 3, 300)] {
+            let random_set = |rng: &mut Pcg64| {
+                let mut set: ARTSet<Vec<u8>> = ARTSet::new();
+                let mut model = std::collections::BTreeSet::new();
+
+                for _ in 0..count {
+                    let key = random_key(rng, alphabet, max_len);
+                    if rng.gen_bool(0.8) {
+                        assert_eq!(model.insert(key.clone()), set.insert(key));
+                    } else {
+                        assert_eq!(model.remove(&key), set.remove(&key));
+                    }
+                }
+
+                assert_eq!(model.len(), set.len());
+                assert!(set.iter().eq(model.iter().cloned()));
+                assert!(set.iter().rev().eq(model.iter().rev().cloned()));
+                (set, model)
+            };
+
+            let (a, a_model) = random_set(&mut rng);
+            let (b, b_model) = random_set(&mut rng);
+
+            for key in [vec![], vec![0], vec![1, 2], vec![3, 3, 3]] {
+                assert_eq!(a_model.contains(&key), a.contains(&key));
+            }
+
+            let lower = vec![1u8];
+            let upper = vec![2u8, 1];
+            assert!(a.range(lower.clone()..upper.clone()).eq(a_model.range(lower..upper).cloned()));
+            assert!(a.prefix_iter(&vec![1u8]).eq(a_model.iter().filter(|k| k.starts_with(&[1])).cloned()));
+
+            // the lockstep descent against a flat merge of the sorted keys
+            let empty = ARTSet::new();
+            for ((x, x_model), (y, y_model)) in [((&a, &a_model), (&b, &b_model)),
+                                                 ((&b, &b_model), (&a, &a_model)),
+                                                 ((&a, &a_model), (&empty, &Default::default()))] {
+                assert!(x.union(y).eq(x_model.union(y_model).cloned()));
+                assert!(x.intersection(y).eq(x_model.intersection(y_model).cloned()));
+                assert!(x.difference(y).eq(x_model.difference(y_model).cloned()));
+                assert!(x.symmetric_difference(y).eq(x_model.symmetric_difference(y_model).cloned()));
+            }
+
+            assert!(a.intersection(&a).eq(a.iter()));
+            assert_eq!(0, a.difference(&a).count());
+            assert_eq!(a, a.union(&a).collect());
         }
     }
 
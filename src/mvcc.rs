@@ -0,0 +1,188 @@
+use crate::iter::Range;
+use crate::keys::{ARTKey, ARTKeyDecode};
+use crate::ARTree;
+
+use std::borrow::Borrow;
+use std::iter::FusedIterator;
+use std::ops::RangeBounds;
+
+/// A commit timestamp. Readers at timestamp `ts` see every write committed
+/// at or before `ts`.
+pub type Timestamp = u64;
+
+/// A map keeping every committed version of its values, for snapshot
+/// isolation on top of `ARTree`.
+///
+/// Each key maps to a chain of versions in timestamp order, in which a
+/// delete is a tombstone version. Old versions stay until `gc` is told that
+/// no reader can see them anymore.
+pub struct MvccARTree<K: ARTKey, V> {
+    tree: ARTree<K, VersionChain<V>>,
+}
+
+/// The versions of one key, oldest first. `None` marks a delete.
+pub struct VersionChain<V> {
+    versions: Vec<(Timestamp, Option<V>)>,
+}
+
+impl<V> Default for VersionChain<V> {
+    fn default() -> Self {
+        VersionChain {
+            versions: Vec::new(),
+        }
+    }
+}
+
+impl<V> VersionChain<V> {
+    /// The value seen by a reader at `ts`, if the key existed then.
+    pub fn at(&self, ts: Timestamp) -> Option<&V> {
+        let visible = self.versions.partition_point(|&(version, _)| version <= ts);
+        self.versions[..visible].last()?.1.as_ref()
+    }
+
+    /// The versions oldest first, with `None` for deletes.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (Timestamp, Option<&V>)> + '_ {
+        self.versions.iter().map(|(ts, value)| (*ts, value.as_ref()))
+    }
+
+    fn write(&mut self, ts: Timestamp, value: Option<V>) {
+        match self.versions.binary_search_by_key(&ts, |&(version, _)| version) {
+            Ok(index) => self.versions[index].1 = value,
+            Err(index) => self.versions.insert(index, (ts, value)),
+        }
+    }
+
+    /// Drops the versions that readers at `oldest_active_ts` or later
+    /// cannot see, returning how many there were.
+    fn prune(&mut self, oldest_active_ts: Timestamp) -> usize {
+        let visible = self.versions.partition_point(|&(version, _)| version <= oldest_active_ts);
+
+        // everything before the version the oldest reader sees is shadowed,
+        // and that version too if it is a tombstone
+        let mut shadowed = visible.saturating_sub(1);
+        if self.versions.get(shadowed).is_some_and(|(version, value)| {
+            *version <= oldest_active_ts && value.is_none()
+        }) {
+            shadowed += 1;
+        }
+
+        self.versions.drain(..shadowed);
+        shadowed
+    }
+
+    pub fn len(&self) -> usize {
+        self.versions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.versions.is_empty()
+    }
+}
+
+impl<K: ARTKey, V> Default for MvccARTree<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: ARTKey, V> MvccARTree<K, V> {
+    pub fn new() -> Self {
+        MvccARTree {
+            tree: ARTree::new(),
+        }
+    }
+
+    /// Writes `value` as the version of `key` committed at `ts`, replacing a
+    /// version with the same timestamp.
+    pub fn insert(&mut self, key: K, value: V, ts: Timestamp) {
+        self.write(key, ts, Some(value));
+    }
+
+    /// Writes a tombstone: readers at `ts` or later no longer see `key`.
+    pub fn delete(&mut self, key: K, ts: Timestamp) {
+        self.write(key, ts, None);
+    }
+
+    fn write(&mut self, key: K, ts: Timestamp, value: Option<V>) {
+        self.tree
+            .entry(key)
+            .or_default()
+            .write(ts, value);
+    }
+
+    pub fn get_at<Q>(&self, key: &Q, ts: Timestamp) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: ARTKey + ?Sized,
+    {
+        self.tree.get(key)?.at(ts)
+    }
+
+    /// All versions of `key`, including tombstones.
+    pub fn versions<Q>(&self, key: &Q) -> Option<&VersionChain<V>>
+    where
+        K: Borrow<Q>,
+        Q: ARTKey + ?Sized,
+    {
+        self.tree.get(key)
+    }
+
+    /// Iterates in key order over the entries in `range` as a reader at `ts`
+    /// sees them.
+    pub fn range_at<T, R>(&self, range: R, ts: Timestamp) -> RangeAt<'_, K, V>
+    where
+        K: Borrow<T>,
+        T: ARTKey + ?Sized,
+        R: RangeBounds<T>,
+    {
+        RangeAt {
+            inner: self.tree.range(range),
+            ts,
+        }
+    }
+}
+
+impl<K: ARTKeyDecode, V> MvccARTree<K, V> {
+    /// Removes the versions that no reader at `oldest_active_ts` or later can
+    /// see, and the keys left without versions. Returns the number of
+    /// removed versions.
+    pub fn gc(&mut self, oldest_active_ts: Timestamp) -> usize {
+        let mut removed = 0;
+        let mut emptied = Vec::new();
+
+        for (key, chain) in self.tree.iter_mut() {
+            removed += chain.prune(oldest_active_ts);
+            if chain.is_empty() {
+                emptied.push(key);
+            }
+        }
+
+        for key in emptied {
+            self.tree.delete(&key);
+        }
+        removed
+    }
+}
+
+pub struct RangeAt<'a, K, V> {
+    inner: Range<'a, K, VersionChain<V>>,
+    ts: Timestamp,
+}
+
+impl<'a, K: ARTKeyDecode, V> Iterator for RangeAt<'a, K, V> {
+    type Item = (K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let ts = self.ts;
+        self.inner.find_map(|(key, chain)| Some((key, chain.at(ts)?)))
+    }
+}
+
+impl<'a, K: ARTKeyDecode, V> DoubleEndedIterator for RangeAt<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let ts = self.ts;
+        self.inner.by_ref().rev().find_map(|(key, chain)| Some((key, chain.at(ts)?)))
+    }
+}
+
+impl<'a, K: ARTKeyDecode, V> FusedIterator for RangeAt<'a, K, V> {}
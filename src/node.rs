@@ -1,64 +1,84 @@
 use crate::keys::ByteKey;
 use std::iter::zip;
 use std::ptr::addr_of_mut;
-use std::simd::u8x16;
-use std::simd::Simd;
 
 use crunchy::{self, unroll};
 
 use auto_impl::auto_impl;
 use enum_dispatch::enum_dispatch;
 
+#[derive(Clone)]
 pub enum ARTNode<V> {
-    Inner(ARTInnerNode<V>, ByteKey, Option<V>),
+    Inner(ARTInnerNode<ARTNode<V>>, ByteKey, Option<V>),
     Leaf(ARTLeaf<V>),
 }
 
 pub type ARTLink<V> = Option<ARTNode<V>>;
 
-pub struct ARTInner4<V> {
+#[derive(Clone)]
+pub struct ARTInner4<C> {
     keys: [Option<u8>; 4],
-    children: [ARTLink<V>; 4],
+    children: [Option<C>; 4],
     children_num: u8,
 }
 
-pub struct ARTInner16<V> {
-    keys: u8x16,
-    children: [ARTLink<V>; 16],
+#[derive(Clone)]
+pub struct ARTInner16<C> {
+    keys: [u8; 16],
+    children: [Option<C>; 16],
     children_num: u8,
 }
 
-pub struct ARTInner48<V> {
+#[derive(Clone)]
+pub struct ARTInner48<C> {
     keys: [Option<u8>; 256],
-    children: [ARTLink<V>; 48],
+    children: [Option<C>; 48],
     children_num: u8,
 }
 
-pub struct ARTInner256<V> {
-    children: [ARTLink<V>; 256],
+#[derive(Clone)]
+pub struct ARTInner256<C> {
+    children: [Option<C>; 256],
     children_num: u16,
 }
 
+#[derive(Clone)]
 pub struct ARTLeaf<V> {
     pkey: ByteKey,
     value: V,
 }
 
 impl<V> ARTNode<V> {
-    fn try_into_leaf_value(self) -> Option<V> {
+    pub fn pkey(&self) -> &[u8] {
+        match self {
+            ARTNode::Inner(_, pkey, _) => pkey,
+            ARTNode::Leaf(leaf) => leaf.pkey(),
+        }
+    }
+
+    pub fn pkey_mut(&mut self) -> &mut ByteKey {
         match self {
-            ARTNode::Leaf(leaf) => Some(leaf.value),
-            ARTNode::Inner(..) => None,
+            ARTNode::Inner(_, pkey, _) => pkey,
+            ARTNode::Leaf(leaf) => leaf.pkey_mut(),
         }
     }
+
+    /// Puts a collapsed parent's prefix and the key byte that led to this
+    /// node in front of the node's own prefix.
+    pub fn prepend_pkey(&mut self, mut prefix: ByteKey, key_byte: u8) {
+        let pkey = self.pkey_mut();
+        prefix.push(key_byte);
+        prefix.extend_from_slice(pkey);
+        *pkey = prefix;
+    }
 }
 
-impl<V> ARTInner4<V> {
+impl<C> ARTInner4<C> {
     fn child_index(&self, key_byte: u8) -> Option<usize> {
         unroll! {
             for i in 0..4 {
-                if self.keys[i as usize] == Some(key_byte) {
-                    return Some(i as usize);
+                if self.keys[i] == Some(key_byte) {
+                    return Some(i);
                 }
             }
         }
@@ -69,6 +89,8 @@ impl<V> ARTInner4<V> {
     fn boxed() -> Box<Self> {
         let mut uninit = Box::<Self>::new_uninit();
         let this = uninit.as_mut_ptr();
+        // SAFETY: every field is written through raw place pointers before
+        // `assume_init`, and no reference to the uninitialized value is made.
         unsafe {
             addr_of_mut!((*this).children_num).write(0);
             unroll! {
@@ -82,28 +104,28 @@ impl<V> ARTInner4<V> {
     }
 }
 
-impl<V> ARTInner16<V> {
+impl<C> ARTInner16<C> {
     fn child_index(&self, key_byte: u8) -> Option<usize> {
         assert!(self.children_num <= 16);
 
-        let key: Simd<u8, 16> = u8x16::splat(key_byte);
-        let cmp = self.keys.lanes_eq(key);
+        // lanes past `children_num` hold stale keys
+        let mask = key_mask(&self.keys, key_byte) & ((1 << self.children_num) - 1);
 
-        for i in 0..self.children_num as usize {
-            if cmp.test(i) {
-                return Some(i);
-            }
+        if mask == 0 {
+            None
+        } else {
+            Some(mask.trailing_zeros() as usize)
         }
-
-        None
     }
 
     fn boxed() -> Box<Self> {
         let mut uninit = Box::<Self>::new_uninit();
         let this = uninit.as_mut_ptr();
+        // SAFETY: every field is written through raw place pointers before
+        // `assume_init`, and no reference to the uninitialized value is made.
         unsafe {
             addr_of_mut!((*this).children_num).write(0);
-            addr_of_mut!((*this).keys).write([0; 16].into());
+            addr_of_mut!((*this).keys).write([0; 16]);
             unroll! {
                 for i in 0..16 {
                     addr_of_mut!((*this).children[i]).write(None);
@@ -114,10 +136,59 @@ impl<V> ARTInner16<V> {
     }
 }
 
-impl<V> ARTInner48<V> {
+/// Returns a bitmask with bit `i` set when `keys[i] == key_byte`.
+#[cfg(feature = "nightly-simd")]
+pub(crate) fn key_mask(keys: &[u8; 16], key_byte: u8) -> u32 {
+    use std::simd::cmp::SimdPartialEq;
+    use std::simd::u8x16;
+
+    u8x16::from_array(*keys).simd_eq(u8x16::splat(key_byte)).to_bitmask() as u32
+}
+
+#[cfg(all(not(feature = "nightly-simd"), target_arch = "x86_64"))]
+pub(crate) fn key_mask(keys: &[u8; 16], key_byte: u8) -> u32 {
+    use std::arch::x86_64::{_mm_cmpeq_epi8, _mm_loadu_si128, _mm_movemask_epi8, _mm_set1_epi8};
+
+    // SAFETY: SSE2 is part of the x86_64 baseline, and the unaligned load
+    // reads exactly the 16 bytes of `keys`.
+    unsafe {
+        let keys = _mm_loadu_si128(keys.as_ptr().cast());
+        let cmp = _mm_cmpeq_epi8(keys, _mm_set1_epi8(key_byte as i8));
+        _mm_movemask_epi8(cmp) as u32
+    }
+}
+
+#[cfg(all(not(feature = "nightly-simd"), target_arch = "aarch64"))]
+pub(crate) fn key_mask(keys: &[u8; 16], key_byte: u8) -> u32 {
+    use std::arch::aarch64::{
+        vaddv_u8, vandq_u8, vceqq_u8, vdupq_n_u8, vget_high_u8, vget_low_u8, vld1q_u8,
+    };
+
+    const LANE_BITS: [u8; 16] = [1, 2, 4, 8, 16, 32, 64, 128, 1, 2, 4, 8, 16, 32, 64, 128];
+
+    // SAFETY: NEON is part of the aarch64 baseline, and both loads read
+    // exactly 16 bytes.
+    unsafe {
+        let cmp = vceqq_u8(vld1q_u8(keys.as_ptr()), vdupq_n_u8(key_byte));
+        let bits = vandq_u8(cmp, vld1q_u8(LANE_BITS.as_ptr()));
+        vaddv_u8(vget_low_u8(bits)) as u32 | (vaddv_u8(vget_high_u8(bits)) as u32) << 8
+    }
+}
+
+#[cfg(all(not(feature = "nightly-simd"),
+          not(any(target_arch = "x86_64", target_arch = "aarch64"))))]
+pub(crate) fn key_mask(keys: &[u8; 16], key_byte: u8) -> u32 {
+    keys.iter()
+        .enumerate()
+        .fold(0, |mask, (i, &key)| mask | ((key == key_byte) as u32) << i)
+}
+
+impl<C> ARTInner48<C> {
     fn boxed() -> Box<Self> {
         let mut uninit = Box::<Self>::new_uninit();
         let this = uninit.as_mut_ptr();
+        // SAFETY: every field is written through raw place pointers before
+        // `assume_init`, and no reference to the uninitialized value is made.
         unsafe {
             addr_of_mut!((*this).children_num).write(0);
             for i in 0..256 {
@@ -134,10 +205,12 @@ impl<V> ARTInner48<V> {
     }
 }
 
-impl<V> ARTInner256<V> {
+impl<C> ARTInner256<C> {
     fn boxed() -> Box<Self> {
         let mut uninit = Box::<Self>::new_uninit();
         let this = uninit.as_mut_ptr();
+        // SAFETY: every field is written through raw place pointers before
+        // `assume_init`, and no reference to the uninitialized value is made.
         unsafe {
             addr_of_mut!((*this).children_num).write(0);
             for i in 0..256 {
@@ -177,6 +250,10 @@ impl<V> ARTLeaf<V> {
         &self.value
     }
 
+    pub fn value_mut(&mut self) -> &mut V {
+        &mut self.value
+    }
+
     pub fn take_value(self) -> V {
         self.value
     }
@@ -188,24 +265,24 @@ impl<V> ARTLeaf<V> {
 
 #[enum_dispatch]
 #[auto_impl(Box)]
-pub trait InnerNode<V> {
-    fn add_child(&mut self, pkey: ByteKey, value: V, key_byte: u8) {
-        self.add_node(ARTNode::Leaf(ARTLeaf::new(pkey, value)), key_byte)
-    }
-
-    fn add_node(&mut self, new_node: ARTNode<V>, key_byte: u8);
-
-    fn find_child_mut(&mut self, key_byte: u8) -> Option<*mut ARTLink<V>>;
-    fn remove_child(&mut self, key_byte: u8) -> Option<V>;
-    fn shrink(self) -> ARTInnerNode<V>;
-    fn find_child(&self, key_byte: u8) -> Option<&ARTNode<V>>;
+pub trait InnerNode<C> {
+    fn add_node(&mut self, new_node: C, key_byte: u8);
+
+    fn find_child_mut(&mut self, key_byte: u8) -> Option<&mut C>;
+    fn take_child(&mut self, key_byte: u8) -> Option<C>;
+    fn children_num(&self) -> usize;
+    fn shrink(self) -> ARTInnerNode<C>;
+    fn find_child(&self, key_byte: u8) -> Option<&C>;
+    fn sorted_children(&self) -> Vec<(u8, &C)>;
+    fn sorted_children_mut(&mut self) -> Vec<(u8, &mut C)>;
+    fn into_sorted_children(self) -> Vec<(u8, C)>;
     fn is_full(&self) -> bool;
     fn is_shrinkable(&self) -> bool;
-    fn grow(self) -> ARTInnerNode<V>;
+    fn grow(self) -> ARTInnerNode<C>;
 }
 
-impl<V> InnerNode<V> for ARTInner4<V> {
-    fn add_node(&mut self, new_node: ARTNode<V>, key_byte: u8) {
+impl<C> InnerNode<C> for ARTInner4<C> {
+    fn add_node(&mut self, new_node: C, key_byte: u8) {
         assert!(!self.is_full());
 
         let num = self.children_num as usize;
@@ -214,12 +291,12 @@ impl<V> InnerNode<V> for ARTInner4<V> {
         self.children_num += 1;
     }
 
-    fn find_child_mut(&mut self, key_byte: u8) -> Option<*mut ARTLink<V>> {
+    fn find_child_mut(&mut self, key_byte: u8) -> Option<&mut C> {
         let i = self.child_index(key_byte)?;
-        Some(&mut self.children[i] as *mut _)
+        self.children[i].as_mut()
     }
 
-    fn remove_child(&mut self, key_byte: u8) -> Option<V> {
+    fn take_child(&mut self, key_byte: u8) -> Option<C> {
         let index = self.child_index(key_byte)?;
         let end = self.children_num as usize - 1;
 
@@ -230,18 +307,46 @@ impl<V> InnerNode<V> for ARTInner4<V> {
 
         self.keys[end] = None;
         self.children_num -= 1;
-        self.children[end].take().unwrap().try_into_leaf_value()
+        self.children[end].take()
+    }
+
+    fn children_num(&self) -> usize {
+        self.children_num as usize
     }
 
-    fn shrink(self) -> ARTInnerNode<V> {
+    fn shrink(self) -> ARTInnerNode<C> {
         panic!("This node cannot shrink!")
     }
 
-    fn find_child(&self, key_byte: u8) -> Option<&ARTNode<V>> {
+    fn find_child(&self, key_byte: u8) -> Option<&C> {
         let i = self.child_index(key_byte)?;
         self.children[i].as_ref()
     }
 
+    fn sorted_children(&self) -> Vec<(u8, &C)> {
+        let mut children: Vec<_> = zip(self.keys, &self.children)
+            .filter_map(|(key, child)| Some((key?, child.as_ref()?)))
+            .collect();
+        children.sort_unstable_by_key(|&(key, _)| key);
+        children
+    }
+
+    fn sorted_children_mut(&mut self) -> Vec<(u8, &mut C)> {
+        let mut children: Vec<_> = zip(self.keys, &mut self.children)
+            .filter_map(|(key, child)| Some((key?, child.as_mut()?)))
+            .collect();
+        children.sort_unstable_by_key(|&(key, _)| key);
+        children
+    }
+
+    fn into_sorted_children(self) -> Vec<(u8, C)> {
+        let mut children: Vec<_> = zip(self.keys, self.children)
+            .filter_map(|(key, child)| Some((key?, child?)))
+            .collect();
+        children.sort_unstable_by_key(|&(key, _)| key);
+        children
+    }
+
     fn is_full(&self) -> bool {
         self.children_num >= 4
     }
@@ -250,7 +355,7 @@ impl<V> InnerNode<V> for ARTInner4<V> {
         false
     }
 
-    fn grow(self) -> ARTInnerNode<V> {
+    fn grow(self) -> ARTInnerNode<C> {
         assert_eq!(self.children_num, 4);
 
         let mut node = ARTInner16::boxed();
@@ -270,8 +375,8 @@ impl<V> InnerNode<V> for ARTInner4<V> {
     }
 }
 
-impl<V> InnerNode<V> for ARTInner16<V> {
-    fn add_node(&mut self, new_node: ARTNode<V>, key_byte: u8) {
+impl<C> InnerNode<C> for ARTInner16<C> {
+    fn add_node(&mut self, new_node: C, key_byte: u8) {
         assert!(!self.is_full());
 
         let num = self.children_num as usize;
@@ -280,12 +385,12 @@ impl<V> InnerNode<V> for ARTInner16<V> {
         self.children_num += 1;
     }
 
-    fn find_child_mut(&mut self, key_byte: u8) -> Option<*mut ARTLink<V>> {
+    fn find_child_mut(&mut self, key_byte: u8) -> Option<&mut C> {
         let index = self.child_index(key_byte)?;
-        Some(&mut self.children[index] as *mut _)
+        self.children[index].as_mut()
     }
 
-    fn remove_child(&mut self, key_byte: u8) -> Option<V> {
+    fn take_child(&mut self, key_byte: u8) -> Option<C> {
         let index = self.child_index(key_byte)?;
         let end = self.children_num as usize - 1;
 
@@ -295,10 +400,14 @@ impl<V> InnerNode<V> for ARTInner16<V> {
         }
 
         self.children_num -= 1;
-        self.children[end].take().unwrap().try_into_leaf_value()
+        self.children[end].take()
     }
 
-    fn shrink(mut self) -> ARTInnerNode<V> {
+    fn children_num(&self) -> usize {
+        self.children_num as usize
+    }
+
+    fn shrink(mut self) -> ARTInnerNode<C> {
         assert_eq!(self.children_num, 4);
 
         let mut node = ARTInner4::boxed();
@@ -314,11 +423,39 @@ impl<V> InnerNode<V> for ARTInner16<V> {
         node.into()
     }
 
-    fn find_child(&self, key_byte: u8) -> Option<&ARTNode<V>> {
+    fn find_child(&self, key_byte: u8) -> Option<&C> {
         let index = self.child_index(key_byte)?;
         self.children[index].as_ref()
     }
 
+    fn sorted_children(&self) -> Vec<(u8, &C)> {
+        let num = self.children_num as usize;
+        let mut children: Vec<_> = zip(&self.keys[..num], &self.children[..num])
+            .filter_map(|(&key, child)| Some((key, child.as_ref()?)))
+            .collect();
+        children.sort_unstable_by_key(|&(key, _)| key);
+        children
+    }
+
+    fn sorted_children_mut(&mut self) -> Vec<(u8, &mut C)> {
+        let num = self.children_num as usize;
+        let mut children: Vec<_> = zip(&self.keys[..num], &mut self.children[..num])
+            .filter_map(|(&key, child)| Some((key, child.as_mut()?)))
+            .collect();
+        children.sort_unstable_by_key(|&(key, _)| key);
+        children
+    }
+
+    fn into_sorted_children(self) -> Vec<(u8, C)> {
+        let num = self.children_num as usize;
+        let mut children: Vec<_> = zip(self.keys, self.children)
+            .take(num)
+            .filter_map(|(key, child)| Some((key, child?)))
+            .collect();
+        children.sort_unstable_by_key(|&(key, _)| key);
+        children
+    }
+
     fn is_full(&self) -> bool {
         self.children_num >= 16
     }
@@ -327,7 +464,7 @@ impl<V> InnerNode<V> for ARTInner16<V> {
         self.children_num <= 4
     }
 
-    fn grow(mut self) -> ARTInnerNode<V> {
+    fn grow(mut self) -> ARTInnerNode<C> {
         assert_eq!(self.children_num, 16);
 
         let mut node = ARTInner48::boxed();
@@ -344,8 +481,8 @@ impl<V> InnerNode<V> for ARTInner16<V> {
     }
 }
 
-impl<V> InnerNode<V> for ARTInner48<V> {
-    fn add_node(&mut self, new_node: ARTNode<V>, key_byte: u8) {
+impl<C> InnerNode<C> for ARTInner48<C> {
+    fn add_node(&mut self, new_node: C, key_byte: u8) {
         assert!(!self.is_full());
 
         self.children[self.children_num as usize] = Some(new_node);
@@ -354,12 +491,12 @@ impl<V> InnerNode<V> for ARTInner48<V> {
         self.children_num += 1;
     }
 
-    fn find_child_mut(&mut self, key_byte: u8) -> Option<*mut ARTLink<V>> {
+    fn find_child_mut(&mut self, key_byte: u8) -> Option<&mut C> {
         let i = self.keys[key_byte as usize]?;
-        Some(&mut self.children[i as usize] as *mut _)
+        self.children[i as usize].as_mut()
     }
 
-    fn remove_child(&mut self, key_byte: u8) -> Option<V> {
+    fn take_child(&mut self, key_byte: u8) -> Option<C> {
         let index = self.keys[key_byte as usize].take()?;
         let end = self.children_num - 1;
 
@@ -371,10 +508,14 @@ impl<V> InnerNode<V> for ARTInner48<V> {
         }
 
         self.children_num -= 1;
-        self.children[end as usize].take().unwrap().try_into_leaf_value()
+        self.children[end as usize].take()
+    }
+
+    fn children_num(&self) -> usize {
+        self.children_num as usize
     }
 
-    fn shrink(mut self) -> ARTInnerNode<V> {
+    fn shrink(mut self) -> ARTInnerNode<C> {
         assert_eq!(self.children_num, 16);
 
         let mut node = ARTInner16::boxed();
@@ -392,11 +533,42 @@ impl<V> InnerNode<V> for ARTInner48<V> {
         node.into()
     }
 
-    fn find_child(&self, key_byte: u8) -> Option<&ARTNode<V>> {
+    fn find_child(&self, key_byte: u8) -> Option<&C> {
         let i = self.keys[key_byte as usize]?;
         self.children[i as usize].as_ref()
     }
 
+    fn sorted_children(&self) -> Vec<(u8, &C)> {
+        self.keys.iter()
+                 .enumerate()
+                 .filter_map(|(key, index)| {
+                     Some((key as u8, self.children[(*index)? as usize].as_ref()?))
+                 })
+                 .collect()
+    }
+
+    fn sorted_children_mut(&mut self) -> Vec<(u8, &mut C)> {
+        let mut slots: Vec<_> = self.children.iter_mut().map(Some).collect();
+
+        self.keys.iter()
+                 .enumerate()
+                 .filter_map(|(key, index)| {
+                     Some((key as u8, slots[(*index)? as usize].take()?.as_mut()?))
+                 })
+                 .collect()
+    }
+
+    fn into_sorted_children(self) -> Vec<(u8, C)> {
+        let mut slots = self.children.map(Some);
+
+        self.keys.iter()
+                 .enumerate()
+                 .filter_map(|(key, index)| {
+                     Some((key as u8, slots[(*index)? as usize].take()??))
+                 })
+                 .collect()
+    }
+
     fn is_full(&self) -> bool {
         self.children_num >= 48
     }
@@ -405,7 +577,7 @@ impl<V> InnerNode<V> for ARTInner48<V> {
         self.children_num <= 16
     }
 
-    fn grow(mut self) -> ARTInnerNode<V> {
+    fn grow(mut self) -> ARTInnerNode<C> {
         assert_eq!(self.children_num, 48);
 
         let mut node = ARTInner256::boxed();
@@ -421,29 +593,27 @@ impl<V> InnerNode<V> for ARTInner48<V> {
     }
 }
 
-impl<V> InnerNode<V> for ARTInner256<V> {
-    fn add_node(&mut self, new_node: ARTNode<V>, key_byte: u8) {
+impl<C> InnerNode<C> for ARTInner256<C> {
+    fn add_node(&mut self, new_node: C, key_byte: u8) {
         self.children[key_byte as usize] = Some(new_node);
         self.children_num += 1;
     }
 
-    fn find_child_mut(&mut self, key_byte: u8) -> Option<*mut ARTLink<V>> {
-        let node = &mut self.children[key_byte as usize];
-
-        if node.is_none() {
-            return None;
-        }
-
-        Some(node as *mut _)
+    fn find_child_mut(&mut self, key_byte: u8) -> Option<&mut C> {
+        self.children[key_byte as usize].as_mut()
     }
 
-    fn remove_child(&mut self, key_byte: u8) -> Option<V> {
+    fn take_child(&mut self, key_byte: u8) -> Option<C> {
         let child = self.children[key_byte as usize].take()?;
         self.children_num -= 1;
-        child.try_into_leaf_value()
+        Some(child)
+    }
+
+    fn children_num(&self) -> usize {
+        self.children_num as usize
     }
 
-    fn shrink(self) -> ARTInnerNode<V> {
+    fn shrink(self) -> ARTInnerNode<C> {
         assert_eq!(self.children_num, 48);
 
         let mut node = ARTInner48::boxed();
@@ -461,10 +631,31 @@ impl<V> InnerNode<V> for ARTInner256<V> {
         node.into()
     }
 
-    fn find_child(&self, key_byte: u8) -> Option<&ARTNode<V>> {
+    fn find_child(&self, key_byte: u8) -> Option<&C> {
         self.children[key_byte as usize].as_ref()
     }
 
+    fn sorted_children(&self) -> Vec<(u8, &C)> {
+        self.children.iter()
+                     .enumerate()
+                     .filter_map(|(key, child)| Some((key as u8, child.as_ref()?)))
+                     .collect()
+    }
+
+    fn sorted_children_mut(&mut self) -> Vec<(u8, &mut C)> {
+        self.children.iter_mut()
+                     .enumerate()
+                     .filter_map(|(key, child)| Some((key as u8, child.as_mut()?)))
+                     .collect()
+    }
+
+    fn into_sorted_children(self) -> Vec<(u8, C)> {
+        self.children.into_iter()
+                     .enumerate()
+                     .filter_map(|(key, child)| Some((key as u8, child?)))
+                     .collect()
+    }
+
     fn is_full(&self) -> bool {
         false
     }
@@ -473,22 +664,40 @@ impl<V> InnerNode<V> for ARTInner256<V> {
         self.children_num <= 48
     }
 
-    fn grow(self) -> ARTInnerNode<V> {
+    fn grow(self) -> ARTInnerNode<C> {
         panic!("This node cannot grow!")
     }
 }
 
-#[enum_dispatch(InnerNode<V>)]
-pub enum ARTInnerNode<V> {
-    Inner4(Box<ARTInner4<V>>),
-    Inner16(Box<ARTInner16<V>>),
-    Inner48(Box<ARTInner48<V>>),
-    Inner256(Box<ARTInner256<V>>),
+#[derive(Clone)]
+#[enum_dispatch(InnerNode<C>)]
+pub enum ARTInnerNode<C> {
+    Inner4(Box<ARTInner4<C>>),
+    Inner16(Box<ARTInner16<C>>),
+    Inner48(Box<ARTInner48<C>>),
+    Inner256(Box<ARTInner256<C>>),
 }
 
-impl<V> ARTInnerNode<V> {
+impl<C> ARTInnerNode<C> {
     pub fn new_inner_4() -> Self {
         let node = ARTInner4::boxed();
         Self::Inner4(node)
     }
+
+    /// Returns an empty node of the smallest type that fits `children_num`
+    /// children, so a node whose children are known upfront never grows.
+    pub fn with_capacity(children_num: usize) -> Self {
+        match children_num {
+            0..=4 => Self::Inner4(ARTInner4::boxed()),
+            5..=16 => Self::Inner16(ARTInner16::boxed()),
+            17..=48 => Self::Inner48(ARTInner48::boxed()),
+            _ => Self::Inner256(ARTInner256::boxed()),
+        }
+    }
+}
+
+impl<V> ARTInnerNode<ARTNode<V>> {
+    pub fn add_child(&mut self, pkey: ByteKey, value: V, key_byte: u8) {
+        self.add_node(ARTNode::Leaf(ARTLeaf::new(pkey, value)), key_byte)
+    }
 }
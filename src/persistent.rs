@@ -0,0 +1,405 @@
+use crate::iter::{NodeRef, RawIter};
+use crate::keys::{ARTKey, ARTKeyDecode, ByteKey};
+use crate::node::{ARTInnerNode, ARTLeaf, InnerNode};
+use crate::tree::encode_bounds;
+
+use std::borrow::Borrow;
+use std::iter::FusedIterator;
+use std::marker::PhantomData;
+use std::mem;
+use std::ops::RangeBounds;
+use std::sync::Arc;
+
+/// A copy-on-write tree whose versions share every node they have in common.
+///
+/// Nodes live behind `Arc`. `insert` and `delete` copy only the nodes on the
+/// path to the key they change, and only those that another version still
+/// holds; unshared nodes are changed in place. `snapshot` (and `clone`) is a
+/// single reference count increment, and a snapshot stays readable, and
+/// unchanged, for as long as it is held.
+pub struct PersistentARTree<K: ARTKey, V> {
+    pub(crate) root: Link<V>,
+    len: usize,
+    _marker: PhantomData<K>,
+}
+
+type Link<V> = Option<Arc<PersistentNode<V>>>;
+
+/// Values are behind their own `Arc`, so copying an inner node for a new
+/// version never copies the value it holds.
+pub(crate) enum PersistentNode<V> {
+    Inner(ARTInnerNode<Arc<PersistentNode<V>>>, ByteKey, Option<Arc<V>>),
+    Leaf(ARTLeaf<Arc<V>>),
+}
+
+impl<V> Clone for PersistentNode<V> {
+    fn clone(&self) -> Self {
+        match self {
+            PersistentNode::Inner(inner, pkey, val) => {
+                PersistentNode::Inner(inner.clone(), pkey.clone(), val.clone())
+            }
+            PersistentNode::Leaf(leaf) => PersistentNode::Leaf(leaf.clone()),
+        }
+    }
+}
+
+impl<V> PersistentNode<V> {
+    fn leaf(pkey: &[u8], value: Arc<V>) -> Arc<Self> {
+        Arc::new(PersistentNode::Leaf(ARTLeaf::new(pkey.to_vec(), value)))
+    }
+
+    fn pkey(&self) -> &[u8] {
+        match self {
+            PersistentNode::Inner(_, pkey, _) => pkey,
+            PersistentNode::Leaf(leaf) => leaf.pkey(),
+        }
+    }
+
+    fn pkey_mut(&mut self) -> &mut ByteKey {
+        match self {
+            PersistentNode::Inner(_, pkey, _) => pkey,
+            PersistentNode::Leaf(leaf) => leaf.pkey_mut(),
+        }
+    }
+}
+
+impl<K: ARTKey, V> Default for PersistentARTree<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: ARTKey, V> Clone for PersistentARTree<K, V> {
+    /// Same as `snapshot`: the clone shares every node with `self`.
+    fn clone(&self) -> Self {
+        PersistentARTree {
+            root: self.root.clone(),
+            len: self.len,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<K: ARTKey, V> PersistentARTree<K, V> {
+    pub fn new() -> Self {
+        PersistentARTree {
+            root: None,
+            len: 0,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the current version of the tree in O(1). Later changes to
+    /// either tree are not visible in the other.
+    pub fn snapshot(&self) -> Self {
+        self.clone()
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: ARTKey + ?Sized,
+    {
+        let key_bytes = key.convert_to_bytes();
+        let key_bytes = key_bytes.as_ref();
+        let mut current = self.root.as_deref();
+        let mut depth: usize = 0;
+
+        while let Some(node) = current {
+            let rest = &key_bytes[depth..];
+
+            match node {
+                PersistentNode::Leaf(leaf) => {
+                    return (leaf.pkey() == rest).then(|| &**leaf.value());
+                }
+                PersistentNode::Inner(inner, pkey, val) => {
+                    if !rest.starts_with(pkey) {
+                        return None;
+                    }
+
+                    depth += pkey.len();
+                    if depth == key_bytes.len() {
+                        return val.as_deref();
+                    }
+
+                    current = inner.find_child(key_bytes[depth]).map(|child| &**child);
+                    depth += 1;
+                }
+            }
+        }
+        None
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ARTKey + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    pub fn iter(&self) -> PersistentIter<'_, K, V> {
+        PersistentIter::new(RawIter::new(self.root.as_deref()))
+    }
+
+    pub fn range<T, R>(&self, range: R) -> PersistentIter<'_, K, V>
+    where
+        K: Borrow<T>,
+        T: ARTKey + ?Sized,
+        R: RangeBounds<T>,
+    {
+        let (lower, upper) = encode_bounds(range);
+        PersistentIter::new(RawIter::with_bounds(self.root.as_deref(), lower, upper))
+    }
+}
+
+impl<K: ARTKey, V: Clone> PersistentARTree<K, V> {
+    /// Returns the replaced value, which is cloned if a snapshot still holds
+    /// it.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let bytes = key.convert_to_bytes();
+        let old = match &mut self.root {
+            Some(root) => insert_at(root, bytes.as_ref(), Arc::new(value)),
+            None => {
+                self.root = Some(PersistentNode::leaf(bytes.as_ref(), Arc::new(value)));
+                None
+            }
+        };
+
+        if old.is_none() {
+            self.len += 1;
+        }
+        old.map(Arc::unwrap_or_clone)
+    }
+
+    /// Returns the removed value, which is cloned if a snapshot still holds
+    /// it.
+    pub fn delete<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ARTKey + ?Sized,
+    {
+        // a missing key must not copy the path to where it would be
+        if !self.contains_key(key) {
+            return None;
+        }
+
+        let bytes = key.convert_to_bytes();
+        let root = self.root.as_mut().expect("removed key is missing");
+        let removed = match &**root {
+            PersistentNode::Leaf(leaf) => {
+                let removed = leaf.value().clone();
+                self.root = None;
+                removed
+            }
+            PersistentNode::Inner(..) => remove_at(root, bytes.as_ref()),
+        };
+
+        self.len -= 1;
+        Some(Arc::unwrap_or_clone(removed))
+    }
+}
+
+impl<K: ARTKey, V: Clone> FromIterator<(K, V)> for PersistentARTree<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut tree = PersistentARTree::new();
+        tree.extend(iter);
+        tree
+    }
+}
+
+impl<K: ARTKey, V: Clone> Extend<(K, V)> for PersistentARTree<K, V> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+/// Inserts at or below `node`, where `key` is what is left of the key after
+/// the nodes above. Returns the replaced value.
+fn insert_at<V>(node: &mut Arc<PersistentNode<V>>, key: &[u8], value: Arc<V>) -> Option<Arc<V>> {
+    let common = common_prefix(node.pkey(), key);
+
+    if let PersistentNode::Leaf(leaf) = &**node {
+        if common == key.len() && common == leaf.pkey().len() {
+            let old = leaf.value().clone();
+            *node = PersistentNode::leaf(key, value);
+            return Some(old);
+        }
+
+        // both keys go below a new node holding their common part
+        let mut inner = ARTInnerNode::new_inner_4();
+        let mut val = None;
+
+        for (pkey, value) in [(leaf.pkey(), leaf.value().clone()), (key, value)] {
+            if pkey.len() == common {
+                val = Some(value);
+            } else {
+                inner.add_node(PersistentNode::leaf(&pkey[common + 1..], value), pkey[common]);
+            }
+        }
+
+        *node = Arc::new(PersistentNode::Inner(inner, key[..common].to_vec(), val));
+        return None;
+    }
+
+    if common < node.pkey().len() {
+        // the key either diverges from the prefix or ends inside it
+        let old_pkey = Arc::make_mut(node).pkey_mut();
+        let byte = old_pkey[common];
+        let pkey: ByteKey = old_pkey.drain(..=common).take(common).collect();
+
+        let mut inner = ARTInnerNode::new_inner_4();
+        let val = if common == key.len() {
+            Some(value)
+        } else {
+            inner.add_node(PersistentNode::leaf(&key[common + 1..], value), key[common]);
+            None
+        };
+
+        let old = mem::replace(node, Arc::new(PersistentNode::Inner(inner, pkey, val)));
+        let Some(PersistentNode::Inner(inner, _, _)) = Arc::get_mut(node) else {
+            unreachable!("the node was just created");
+        };
+        inner.add_node(old, byte);
+        return None;
+    }
+
+    let PersistentNode::Inner(inner, pkey, val) = Arc::make_mut(node) else {
+        unreachable!("leaves were handled above");
+    };
+
+    let rest = &key[pkey.len()..];
+    let Some(&byte) = rest.first() else {
+        return val.replace(value);
+    };
+
+    if let Some(child) = inner.find_child_mut(byte) {
+        return insert_at(child, &rest[1..], value);
+    }
+
+    if inner.is_full() {
+        let full = mem::replace(inner, ARTInnerNode::new_inner_4());
+        *inner = full.grow();
+    }
+
+    inner.add_node(PersistentNode::leaf(&rest[1..], value), byte);
+    None
+}
+
+/// Removes a key that is known to be at or below the inner node `node` and
+/// returns its value.
+fn remove_at<V>(node: &mut Arc<PersistentNode<V>>, key: &[u8]) -> Arc<V> {
+    let PersistentNode::Inner(inner, pkey, val) = Arc::make_mut(node) else {
+        unreachable!("leaves are removed through their parent");
+    };
+
+    let rest = &key[pkey.len()..];
+    let removed = match rest.first() {
+        None => val.take().expect("removed key is missing"),
+        Some(&byte) => {
+            let child = inner.find_child_mut(byte).expect("removed key is missing");
+
+            if let PersistentNode::Inner(..) = &**child {
+                remove_at(child, &rest[1..])
+            } else {
+                match inner.take_child(byte).as_deref() {
+                    Some(PersistentNode::Leaf(leaf)) => leaf.value().clone(),
+                    _ => unreachable!("child was checked to be a leaf"),
+                }
+            }
+        }
+    };
+
+    // same invariants as `ARTree`'s `compact`
+    match (inner.children_num(), val.take()) {
+        (0, None) => unreachable!("inner nodes hold at least two entries"),
+        (0, Some(val)) => *node = Arc::new(PersistentNode::Leaf(ARTLeaf::new(mem::take(pkey), val))),
+        (1, None) => {
+            let (byte, _) = inner.sorted_children()[0];
+            let mut child = inner.take_child(byte).expect("missing only child");
+
+            let mut prefix = mem::take(pkey);
+            prefix.push(byte);
+            let child_pkey = Arc::make_mut(&mut child).pkey_mut();
+            prefix.extend_from_slice(child_pkey);
+            *child_pkey = prefix;
+
+            *node = child;
+        }
+        (_, kept) => {
+            *val = kept;
+
+            if inner.is_shrinkable() {
+                let sparse = mem::replace(inner, ARTInnerNode::new_inner_4());
+                *inner = sparse.shrink();
+            }
+        }
+    }
+
+    removed
+}
+
+fn common_prefix(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(a, b)| a == b).count()
+}
+
+impl<'a, V> NodeRef for &'a PersistentNode<V> {
+    type Value = &'a V;
+
+    fn pkey(&self) -> &[u8] {
+        PersistentNode::pkey(self)
+    }
+
+    fn open(self) -> (Option<Self::Value>, Vec<(u8, Self)>) {
+        match self {
+            PersistentNode::Inner(inner, _, val) => {
+                let children = inner.sorted_children()
+                                    .into_iter()
+                                    .map(|(key_byte, child)| (key_byte, &**child))
+                                    .collect();
+                (val.as_deref(), children)
+            }
+            PersistentNode::Leaf(leaf) => (Some(&**leaf.value()), Vec::new()),
+        }
+    }
+}
+
+pub struct PersistentIter<'a, K, V> {
+    raw: RawIter<&'a PersistentNode<V>>,
+    _marker: PhantomData<K>,
+}
+
+impl<'a, K, V> PersistentIter<'a, K, V> {
+    fn new(raw: RawIter<&'a PersistentNode<V>>) -> Self {
+        PersistentIter {
+            raw,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, K: ARTKeyDecode, V> Iterator for PersistentIter<'a, K, V> {
+    type Item = (K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.raw.next().map(|(key, val)| (K::convert_from_bytes(&key), val))
+    }
+}
+
+impl<'a, K: ARTKeyDecode, V> DoubleEndedIterator for PersistentIter<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.raw.next_back().map(|(key, val)| (K::convert_from_bytes(&key), val))
+    }
+}
+
+impl<'a, K: ARTKeyDecode, V> FusedIterator for PersistentIter<'a, K, V> {}
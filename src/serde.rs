@@ -0,0 +1,70 @@
+use crate::build::SortedBuilder;
+use crate::keys::{ARTKey, ARTKeyDecode};
+use crate::ARTree;
+
+use serde::de::{Deserialize, Deserializer, MapAccess, Visitor};
+use serde::ser::{Serialize, SerializeMap, Serializer};
+
+use std::fmt;
+use std::marker::PhantomData;
+
+impl<K, V> Serialize for ARTree<K, V>
+where
+    K: ARTKeyDecode + Serialize,
+    V: Serialize,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.len()))?;
+        for (key, value) in self.iter() {
+            map.serialize_entry(&key, value)?;
+        }
+        map.end()
+    }
+}
+
+impl<'de, K, V> Deserialize<'de> for ARTree<K, V>
+where
+    K: ARTKey + Deserialize<'de>,
+    V: Deserialize<'de>,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(ARTreeVisitor(PhantomData))
+    }
+}
+
+struct ARTreeVisitor<K, V>(PhantomData<(K, V)>);
+
+impl<'de, K, V> Visitor<'de> for ARTreeVisitor<K, V>
+where
+    K: ARTKey + Deserialize<'de>,
+    V: Deserialize<'de>,
+{
+    type Value = ARTree<K, V>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("a map")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut access: A) -> Result<Self::Value, A::Error> {
+        // a serialized tree is already in key order, so it is rebuilt bottom-up;
+        // maps from elsewhere fall back to inserting from the first key out of
+        // order, with later duplicates winning like in `BTreeMap`
+        let mut builder = SortedBuilder::new();
+
+        while let Some((key, value)) = access.next_entry::<K, V>()? {
+            let bytes = key.convert_to_bytes().as_ref().to_vec();
+
+            if let Err((_, value)) = builder.push(bytes, value) {
+                let mut tree = ARTree::from_builder(builder);
+                tree.insert(key, value);
+
+                while let Some((key, value)) = access.next_entry()? {
+                    tree.insert(key, value);
+                }
+                return Ok(tree);
+            }
+        }
+
+        Ok(ARTree::from_builder(builder))
+    }
+}
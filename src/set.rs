@@ -0,0 +1,458 @@
+use crate::iter::{self, RawIter};
+use crate::keys::{ARTKey, ARTKeyDecode, ByteKey};
+use crate::node::{ARTNode, InnerNode};
+use crate::ARTree;
+
+use std::borrow::Borrow;
+use std::fmt::{self, Debug};
+use std::iter::FusedIterator;
+use std::marker::PhantomData;
+use std::ops::RangeBounds;
+
+/// An ordered set of keys, stored as an `ARTree` without values.
+///
+/// No separate leaf representation was added: the set uses the tree's own
+/// nodes with `()` values. Set operations descend both tries at once and
+/// only decode the keys they yield.
+pub struct ARTSet<K: ARTKey> {
+    tree: ARTree<K, ()>,
+}
+
+impl<K: ARTKey> Default for ARTSet<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: ARTKey> ARTSet<K> {
+    pub fn new() -> Self {
+        ARTSet {
+            tree: ARTree::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.tree.clear();
+    }
+
+    /// Adds `key`, returning whether it was not in the set yet.
+    pub fn insert(&mut self, key: K) -> bool {
+        self.tree.insert(key, ()).is_none()
+    }
+
+    /// Removes `key`, returning whether it was in the set.
+    pub fn remove<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ARTKey + ?Sized,
+    {
+        self.tree.delete(key).is_some()
+    }
+
+    pub fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ARTKey + ?Sized,
+    {
+        self.tree.contains_key(key)
+    }
+
+    pub fn iter(&self) -> Iter<'_, K> {
+        Iter {
+            inner: self.tree.keys(),
+        }
+    }
+
+    pub fn range<T, R>(&self, range: R) -> Range<'_, K>
+    where
+        K: Borrow<T>,
+        T: ARTKey + ?Sized,
+        R: RangeBounds<T>,
+    {
+        Range {
+            inner: self.tree.range(range),
+        }
+    }
+
+    /// Iterates in key order over the keys whose encoding starts with the
+    /// encoding of `prefix`.
+    pub fn prefix_iter<P: ARTKey + ?Sized>(&self, prefix: &P) -> Iter<'_, K> {
+        Iter {
+            inner: iter::Keys::new(self.tree.prefix_iter(prefix)),
+        }
+    }
+
+    /// The keys in `self`, `other` or both, in order.
+    pub fn union<'a>(&'a self, other: &'a ARTSet<K>) -> Union<'a, K> {
+        let keep = Keep {
+            left_only: true,
+            right_only: true,
+            both: true,
+        };
+
+        Union {
+            lockstep: Lockstep::new(self, other, keep),
+        }
+    }
+
+    /// The keys in both `self` and `other`, in order.
+    pub fn intersection<'a>(&'a self, other: &'a ARTSet<K>) -> Intersection<'a, K> {
+        let keep = Keep {
+            left_only: false,
+            right_only: false,
+            both: true,
+        };
+
+        Intersection {
+            lockstep: Lockstep::new(self, other, keep),
+        }
+    }
+
+    /// The keys in `self` but not in `other`, in order.
+    pub fn difference<'a>(&'a self, other: &'a ARTSet<K>) -> Difference<'a, K> {
+        let keep = Keep {
+            left_only: true,
+            right_only: false,
+            both: false,
+        };
+
+        Difference {
+            lockstep: Lockstep::new(self, other, keep),
+        }
+    }
+
+    /// The keys in exactly one of `self` and `other`, in order.
+    pub fn symmetric_difference<'a>(&'a self, other: &'a ARTSet<K>)
+        -> SymmetricDifference<'a, K> {
+        let keep = Keep {
+            left_only: true,
+            right_only: true,
+            both: false,
+        };
+
+        SymmetricDifference {
+            lockstep: Lockstep::new(self, other, keep),
+        }
+    }
+}
+
+impl<K: ARTKey> Clone for ARTSet<K> {
+    fn clone(&self) -> Self {
+        ARTSet {
+            tree: self.tree.clone(),
+        }
+    }
+}
+
+impl<K: ARTKey> PartialEq for ARTSet<K> {
+    fn eq(&self, other: &Self) -> bool {
+        self.tree == other.tree
+    }
+}
+
+impl<K: ARTKey> Eq for ARTSet<K> {}
+
+impl<K: ARTKeyDecode + Debug> Debug for ARTSet<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl<K: ARTKey> FromIterator<K> for ARTSet<K> {
+    fn from_iter<I: IntoIterator<Item = K>>(iter: I) -> Self {
+        let mut set = ARTSet::new();
+        set.extend(iter);
+        set
+    }
+}
+
+impl<K: ARTKey> Extend<K> for ARTSet<K> {
+    fn extend<I: IntoIterator<Item = K>>(&mut self, iter: I) {
+        for key in iter {
+            self.insert(key);
+        }
+    }
+}
+
+impl<'a, K: ARTKeyDecode> IntoIterator for &'a ARTSet<K> {
+    type Item = K;
+    type IntoIter = Iter<'a, K>;
+
+    fn into_iter(self) -> Iter<'a, K> {
+        self.iter()
+    }
+}
+
+pub struct Iter<'a, K> {
+    inner: iter::Keys<'a, K, ()>,
+}
+
+pub struct Range<'a, K> {
+    inner: iter::Range<'a, K, ()>,
+}
+
+impl<'a, K: ARTKeyDecode> Iterator for Iter<'a, K> {
+    type Item = K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+}
+
+impl<'a, K: ARTKeyDecode> DoubleEndedIterator for Iter<'a, K> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back()
+    }
+}
+
+impl<'a, K: ARTKeyDecode> FusedIterator for Iter<'a, K> {}
+
+impl<'a, K: ARTKeyDecode> Iterator for Range<'a, K> {
+    type Item = K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(key, _)| key)
+    }
+}
+
+impl<'a, K: ARTKeyDecode> DoubleEndedIterator for Range<'a, K> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|(key, _)| key)
+    }
+}
+
+impl<'a, K: ARTKeyDecode> FusedIterator for Range<'a, K> {}
+
+/// Which keys a set operation yields, by the sets that hold them.
+#[derive(Clone, Copy)]
+struct Keep {
+    left_only: bool,
+    right_only: bool,
+    both: bool,
+}
+
+/// A subtree whose first `skip` compressed prefix bytes were already
+/// matched against the other set.
+type Cursor<'a> = (&'a ARTNode<()>, usize);
+
+enum Step<'a> {
+    /// Subtrees of both sets that sit below the same key bytes.
+    Pair(ByteKey, Cursor<'a>, Cursor<'a>),
+    /// A subtree that only one set has below its key bytes.
+    Subtree(RawIter<&'a ARTNode<()>>),
+    Key(ByteKey),
+}
+
+/// Descends both tries at once. Compressed prefixes are matched byte by
+/// byte and children are merged by key byte, so a subtree that only one
+/// set has is either yielded whole or skipped without being visited.
+struct Lockstep<'a, K> {
+    steps: Vec<Step<'a>>,
+    keep: Keep,
+    _marker: PhantomData<K>,
+}
+
+impl<'a, K: ARTKey> Lockstep<'a, K> {
+    fn new(left: &'a ARTSet<K>, right: &'a ARTSet<K>, keep: Keep) -> Self {
+        let mut lockstep = Lockstep {
+            steps: Vec::new(),
+            keep,
+            _marker: PhantomData,
+        };
+
+        match (left.tree.root.as_ref(), right.tree.root.as_ref()) {
+            (Some(left), Some(right)) => {
+                lockstep.steps.push(Step::Pair(Vec::new(), (left, 0), (right, 0)));
+            }
+            (Some(left), None) if keep.left_only => {
+                lockstep.steps.push(subtree(Vec::new(), (left, 0)));
+            }
+            (None, Some(right)) if keep.right_only => {
+                lockstep.steps.push(subtree(Vec::new(), (right, 0)));
+            }
+            _ => {}
+        }
+        lockstep
+    }
+}
+
+impl<'a, K> Lockstep<'a, K> {
+    /// Matches the remaining prefixes of a pair and schedules what lies
+    /// below the point where they end or part.
+    fn expand(&mut self, mut path: ByteKey, left: Cursor<'a>, right: Cursor<'a>) {
+        let common = common_prefix(&left.0.pkey()[left.1..], &right.0.pkey()[right.1..]);
+        path.extend_from_slice(&left.0.pkey()[left.1..left.1 + common]);
+
+        let (in_left, left_children) = open_at(left, common);
+        let (in_right, right_children) = open_at(right, common);
+
+        let mut steps = Vec::new();
+        if self.keeps(in_left, in_right) {
+            steps.push(Step::Key(path.clone()));
+        }
+
+        let mut left_children = left_children.into_iter().peekable();
+        let mut right_children = right_children.into_iter().peekable();
+
+        loop {
+            let (byte, left, right) = match (left_children.peek(), right_children.peek()) {
+                (None, None) => break,
+                (Some(&(l, _)), Some(&(r, _))) if l == r => {
+                    (l, left_children.next(), right_children.next())
+                }
+                (Some(&(l, _)), Some(&(r, _))) if l < r => (l, left_children.next(), None),
+                (Some(&(l, _)), None) => (l, left_children.next(), None),
+                (_, Some(&(r, _))) => (r, None, right_children.next()),
+            };
+
+            let child_path = || {
+                let mut child_path = path.clone();
+                child_path.push(byte);
+                child_path
+            };
+
+            match (left, right) {
+                (Some((_, left)), Some((_, right))) => {
+                    steps.push(Step::Pair(child_path(), left, right));
+                }
+                (Some((_, left)), None) if self.keep.left_only => {
+                    steps.push(subtree(child_path(), left));
+                }
+                (None, Some((_, right))) if self.keep.right_only => {
+                    steps.push(subtree(child_path(), right));
+                }
+                _ => {}
+            }
+        }
+
+        self.steps.extend(steps.into_iter().rev());
+    }
+
+    fn keeps(&self, in_left: bool, in_right: bool) -> bool {
+        match (in_left, in_right) {
+            (true, true) => self.keep.both,
+            (true, false) => self.keep.left_only,
+            (false, true) => self.keep.right_only,
+            (false, false) => false,
+        }
+    }
+
+    fn next(&mut self) -> Option<ByteKey> {
+        while let Some(step) = self.steps.pop() {
+            match step {
+                Step::Key(key) => return Some(key),
+                Step::Subtree(mut raw) => {
+                    if let Some((key, _)) = raw.next() {
+                        self.steps.push(Step::Subtree(raw));
+                        return Some(key);
+                    }
+                }
+                Step::Pair(path, left, right) => self.expand(path, left, right),
+            }
+        }
+        None
+    }
+
+    fn next_decoded(&mut self) -> Option<K>
+    where
+        K: ARTKeyDecode,
+    {
+        self.next().map(|key| K::convert_from_bytes(&key))
+    }
+}
+
+/// Looks at a subtree `depth` bytes further into its compressed prefix:
+/// whether a key ends there, and the children below that point. Inside the
+/// prefix the subtree itself is the only child.
+fn open_at((node, skip): Cursor<'_>, depth: usize) -> (bool, Vec<(u8, Cursor<'_>)>) {
+    let pkey = node.pkey();
+
+    if skip + depth < pkey.len() {
+        return (false, vec![(pkey[skip + depth], (node, skip + depth + 1))]);
+    }
+
+    match node {
+        ARTNode::Inner(inner, _, val) => {
+            let children = inner.sorted_children()
+                                .into_iter()
+                                .map(|(byte, child)| (byte, (child, 0)))
+                                .collect();
+            (val.is_some(), children)
+        }
+        ARTNode::Leaf(_) => (true, Vec::new()),
+    }
+}
+
+/// Yields a whole subtree; `path` holds the key bytes up to the cursor.
+fn subtree(mut path: ByteKey, (node, skip): Cursor<'_>) -> Step<'_> {
+    path.truncate(path.len() - skip);
+    Step::Subtree(RawIter::new_at(Some(node), path))
+}
+
+fn common_prefix(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(a, b)| a == b).count()
+}
+
+pub struct Union<'a, K> {
+    lockstep: Lockstep<'a, K>,
+}
+
+pub struct Intersection<'a, K> {
+    lockstep: Lockstep<'a, K>,
+}
+
+pub struct Difference<'a, K> {
+    lockstep: Lockstep<'a, K>,
+}
+
+pub struct SymmetricDifference<'a, K> {
+    lockstep: Lockstep<'a, K>,
+}
+
+impl<'a, K: ARTKeyDecode> Iterator for Union<'a, K> {
+    type Item = K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.lockstep.next_decoded()
+    }
+}
+
+impl<'a, K: ARTKeyDecode> FusedIterator for Union<'a, K> {}
+
+impl<'a, K: ARTKeyDecode> Iterator for Intersection<'a, K> {
+    type Item = K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.lockstep.next_decoded()
+    }
+}
+
+impl<'a, K: ARTKeyDecode> FusedIterator for Intersection<'a, K> {}
+
+impl<'a, K: ARTKeyDecode> Iterator for Difference<'a, K> {
+    type Item = K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.lockstep.next_decoded()
+    }
+}
+
+impl<'a, K: ARTKeyDecode> FusedIterator for Difference<'a, K> {}
+
+impl<'a, K: ARTKeyDecode> Iterator for SymmetricDifference<'a, K> {
+    type Item = K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.lockstep.next_decoded()
+    }
+}
+
+impl<'a, K: ARTKeyDecode> FusedIterator for SymmetricDifference<'a, K> {}
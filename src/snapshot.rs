@@ -0,0 +1,285 @@
+use crate::keys::ARTKey;
+use crate::node::{ARTInnerNode, ARTNode, InnerNode};
+use crate::ARTree;
+
+use crc32fast::Hasher;
+
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Write};
+
+// An image starts with a 16 byte header (magic, version, reserved) and ends
+// with a 20 byte footer (entry count, root offset, CRC-32 of everything
+// before the checksum). All integers are little-endian.
+//
+// Nodes are written children first, so every child offset is smaller than its
+// parent's. A node starts with its tag, the `u32` length of its compressed
+// prefix and the prefix itself. Leaves follow with their value, inner nodes
+// with a presence byte, their optional value, a `u16` child count and the
+// body of the matching in-memory node:
+//
+// * Node4 and Node16: 4 or 16 key bytes, then as many `u64` child offsets,
+//   both sorted by key byte,
+// * Node48: a 256 byte index holding child slots (`NODE48_EMPTY` if unused),
+//   then 48 `u64` child offsets,
+// * Node256: 256 `u64` child offsets, 0 for missing children.
+//
+// Values are written as a `u32` length followed by the `FrozenValue` bytes.
+pub(crate) const MAGIC: [u8; 8] = *b"RARTSNAP";
+pub(crate) const VERSION: u32 = 1;
+pub(crate) const HEADER_LEN: usize = 16;
+pub(crate) const FOOTER_LEN: usize = 20;
+
+pub(crate) const TAG_LEAF: u8 = 0;
+pub(crate) const TAG_INNER4: u8 = 1;
+pub(crate) const TAG_INNER16: u8 = 2;
+pub(crate) const TAG_INNER48: u8 = 3;
+pub(crate) const TAG_INNER256: u8 = 4;
+
+pub(crate) const NODE48_EMPTY: u8 = u8::MAX;
+
+/// A value type that can be stored in a snapshot image and read back without
+/// copying it out of the image.
+pub trait FrozenValue {
+    /// What reading a value out of an image borrowing `'a` yields.
+    type Ref<'a>;
+
+    fn freeze(&self, out: &mut Vec<u8>);
+    fn thaw(bytes: &[u8]) -> Self::Ref<'_>;
+}
+
+macro_rules! FrozenValueNumImpl {
+    ($($t:ty),*) => {
+        $(
+            impl FrozenValue for $t {
+                type Ref<'a> = $t;
+
+                fn freeze(&self, out: &mut Vec<u8>) {
+                    out.extend_from_slice(&self.to_le_bytes());
+                }
+
+                fn thaw(bytes: &[u8]) -> $t {
+                    <$t>::from_le_bytes(bytes.try_into().expect("corrupted snapshot value"))
+                }
+            }
+        )*
+    }
+}
+
+FrozenValueNumImpl!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128, f32, f64);
+
+impl FrozenValue for bool {
+    type Ref<'a> = bool;
+
+    fn freeze(&self, out: &mut Vec<u8>) {
+        out.push(*self as u8);
+    }
+
+    fn thaw(bytes: &[u8]) -> bool {
+        bytes[0] != 0
+    }
+}
+
+impl FrozenValue for () {
+    type Ref<'a> = ();
+
+    fn freeze(&self, _out: &mut Vec<u8>) {}
+
+    fn thaw(_bytes: &[u8]) {}
+}
+
+impl FrozenValue for String {
+    type Ref<'a> = &'a str;
+
+    fn freeze(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self.as_bytes());
+    }
+
+    fn thaw(bytes: &[u8]) -> &str {
+        std::str::from_utf8(bytes).expect("corrupted snapshot value")
+    }
+}
+
+impl FrozenValue for Vec<u8> {
+    type Ref<'a> = &'a [u8];
+
+    fn freeze(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self);
+    }
+
+    fn thaw(bytes: &[u8]) -> &[u8] {
+        bytes
+    }
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    /// The data does not start with the snapshot magic bytes.
+    NotASnapshot,
+    UnsupportedVersion(u32),
+    /// The image is cut short or its footer points outside of it.
+    Truncated,
+    ChecksumMismatch,
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Io(err) => write!(f, "snapshot I/O error: {}", err),
+            SnapshotError::NotASnapshot => f.write_str("not an ARTree snapshot"),
+            SnapshotError::UnsupportedVersion(version) => {
+                write!(f, "unsupported snapshot version {}", version)
+            }
+            SnapshotError::Truncated => f.write_str("snapshot is truncated"),
+            SnapshotError::ChecksumMismatch => f.write_str("snapshot checksum mismatch"),
+        }
+    }
+}
+
+impl Error for SnapshotError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SnapshotError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SnapshotError {
+    fn from(err: io::Error) -> Self {
+        SnapshotError::Io(err)
+    }
+}
+
+impl<K: ARTKey, V: FrozenValue> ARTree<K, V> {
+    /// Writes the tree as a snapshot image, which `FrozenARTree` reads in
+    /// place. The writer is not buffered here.
+    pub fn write_snapshot<W: Write>(&self, writer: W) -> io::Result<()> {
+        let mut out = SnapshotWriter {
+            out: writer,
+            offset: 0,
+            hasher: Hasher::new(),
+        };
+
+        out.write(&MAGIC)?;
+        out.write(&VERSION.to_le_bytes())?;
+        out.write(&[0; 4])?;
+
+        let root = match &self.root {
+            Some(node) => out.write_node(node)?,
+            None => 0,
+        };
+
+        out.write(&(self.len as u64).to_le_bytes())?;
+        out.write(&root.to_le_bytes())?;
+
+        let checksum = out.hasher.finalize();
+        out.out.write_all(&checksum.to_le_bytes())?;
+        out.out.flush()
+    }
+}
+
+struct SnapshotWriter<W> {
+    out: W,
+    offset: u64,
+    hasher: Hasher,
+}
+
+impl<W: Write> SnapshotWriter<W> {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.out.write_all(bytes)?;
+        self.hasher.update(bytes);
+        self.offset += bytes.len() as u64;
+        Ok(())
+    }
+
+    /// Writes the subtree rooted at `node` and returns the node's offset.
+    fn write_node<V: FrozenValue>(&mut self, node: &ARTNode<V>) -> io::Result<u64> {
+        let mut buf = Vec::new();
+
+        match node {
+            ARTNode::Leaf(leaf) => {
+                buf.push(TAG_LEAF);
+                write_bytes(&mut buf, leaf.pkey());
+                write_value(&mut buf, leaf.value());
+            }
+            ARTNode::Inner(inner, pkey, val) => {
+                let mut children = Vec::with_capacity(inner.children_num());
+                for (key_byte, child) in inner.sorted_children() {
+                    children.push((key_byte, self.write_node(child)?));
+                }
+
+                buf.push(match inner {
+                    ARTInnerNode::Inner4(_) => TAG_INNER4,
+                    ARTInnerNode::Inner16(_) => TAG_INNER16,
+                    ARTInnerNode::Inner48(_) => TAG_INNER48,
+                    ARTInnerNode::Inner256(_) => TAG_INNER256,
+                });
+                write_bytes(&mut buf, pkey);
+
+                buf.push(val.is_some() as u8);
+                if let Some(val) = val {
+                    write_value(&mut buf, val);
+                }
+
+                buf.extend_from_slice(&(children.len() as u16).to_le_bytes());
+                match inner {
+                    ARTInnerNode::Inner4(_) => write_sparse(&mut buf, &children, 4),
+                    ARTInnerNode::Inner16(_) => write_sparse(&mut buf, &children, 16),
+                    ARTInnerNode::Inner48(_) => {
+                        let mut index = [NODE48_EMPTY; 256];
+                        for (slot, &(key_byte, _)) in children.iter().enumerate() {
+                            index[key_byte as usize] = slot as u8;
+                        }
+                        buf.extend_from_slice(&index);
+                        write_offsets(&mut buf, children.iter().map(|&(_, offset)| offset), 48);
+                    }
+                    ARTInnerNode::Inner256(_) => {
+                        let mut offsets = [0; 256];
+                        for &(key_byte, offset) in children.iter() {
+                            offsets[key_byte as usize] = offset;
+                        }
+                        write_offsets(&mut buf, offsets.into_iter(), 256);
+                    }
+                }
+            }
+        }
+
+        let offset = self.offset;
+        self.write(&buf)?;
+        Ok(offset)
+    }
+}
+
+fn write_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    buf.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+    buf.extend_from_slice(bytes);
+}
+
+fn write_value<V: FrozenValue>(buf: &mut Vec<u8>, value: &V) {
+    let start = buf.len();
+    buf.extend_from_slice(&[0; 4]);
+    value.freeze(buf);
+
+    let len = (buf.len() - start - 4) as u32;
+    buf[start..start + 4].copy_from_slice(&len.to_le_bytes());
+}
+
+/// Writes the key bytes and offsets of a Node4 or Node16, padded to `slots`.
+fn write_sparse(buf: &mut Vec<u8>, children: &[(u8, u64)], slots: usize) {
+    let mut keys = vec![0; slots];
+    for (key, &(key_byte, _)) in keys.iter_mut().zip(children) {
+        *key = key_byte;
+    }
+    buf.extend_from_slice(&keys);
+    write_offsets(buf, children.iter().map(|&(_, offset)| offset), slots);
+}
+
+fn write_offsets(buf: &mut Vec<u8>, offsets: impl Iterator<Item = u64>, slots: usize) {
+    let start = buf.len();
+    for offset in offsets {
+        buf.extend_from_slice(&offset.to_le_bytes());
+    }
+    buf.resize(start + slots * 8, 0);
+}
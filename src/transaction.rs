@@ -0,0 +1,86 @@
+use crate::iter::RawIter;
+use crate::keys::ARTKey;
+use crate::ARTree;
+
+use std::borrow::Borrow;
+
+/// A set of changes to an `ARTree` that is applied all at once.
+///
+/// Writes are buffered in the transaction and reads see them before the
+/// tree's own entries. Nothing reaches the tree until `commit`, and a
+/// transaction that is rolled back or dropped leaves it untouched. The
+/// transaction borrows the tree exclusively, so no reader can observe a
+/// commit halfway.
+///
+/// For the same reason there are no conflicts to check: nothing else can
+/// write to the tree while the transaction is open. Transactions over
+/// `ConcurrentARTree`, which would have to validate their reads at commit,
+/// are deliberately not provided.
+pub struct Transaction<'a, K: ARTKey, V> {
+    tree: &'a mut ARTree<K, V>,
+    /// The pending writes, with `None` for deletes.
+    writes: ARTree<K, Option<V>>,
+}
+
+impl<K: ARTKey, V> ARTree<K, V> {
+    pub fn transaction(&mut self) -> Transaction<'_, K, V> {
+        Transaction {
+            tree: self,
+            writes: ARTree::new(),
+        }
+    }
+}
+
+impl<'a, K: ARTKey, V> Transaction<'a, K, V> {
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: ARTKey + ?Sized,
+    {
+        match self.writes.get(key) {
+            Some(write) => write.as_ref(),
+            None => self.tree.get(key),
+        }
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ARTKey + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    pub fn insert(&mut self, key: K, value: V) {
+        self.writes.insert(key, Some(value));
+    }
+
+    pub fn delete<Q>(&mut self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: ARTKey + ?Sized,
+    {
+        let bytes = key.convert_to_bytes();
+        self.writes.insert_encoded(bytes.as_ref(), None);
+    }
+
+    /// Number of keys written or deleted so far.
+    pub fn pending(&self) -> usize {
+        self.writes.len()
+    }
+
+    /// Applies every buffered write to the tree.
+    pub fn commit(self) {
+        let mut writes = RawIter::new(self.writes.root);
+
+        while let Some((key, write)) = writes.next() {
+            match write {
+                Some(value) => self.tree.insert_encoded(&key, value),
+                None => self.tree.delete_encoded(&key),
+            };
+        }
+    }
+
+    /// Discards every buffered write, the same as dropping the transaction.
+    pub fn rollback(self) {}
+}
@@ -1,8 +1,14 @@
-use crate::keys::{compare_leaf_keys, compare_pkeys, ARTKey, LeafKeyComp, PartialKeyComp, ByteKey};
-use crate::node::{ARTInnerNode, ARTLeaf, ARTNode, InnerNode};
+use crate::entry::{Entry, OccupiedEntry, VacantEntry};
+use crate::keys::{compare_leaf_keys, compare_pkeys, ARTKey, ARTKeyDecode, LeafKeyComp, PartialKeyComp, ByteKey};
+use crate::iter::{IntoIter, Iter, IterMut, Keys, Range, RangeMut, RawIter, Values};
+use crate::node::{ARTInnerNode, ARTLeaf, ARTLink, ARTNode, InnerNode};
 use crate::ARTree;
 
+use std::borrow::Borrow;
+use std::fmt::{self, Debug};
+use std::hash::{Hash, Hasher};
 use std::mem::swap;
+use std::ops::{Bound, Index, RangeBounds};
 
 impl<K: ARTKey, V> Default for ARTree<K, V> {
     fn default() -> Self {
@@ -14,213 +20,87 @@ impl<K: ARTKey, V> ARTree<K, V> {
     pub fn new() -> Self {
         ARTree {
             root: None,
+            len: 0,
             _marker: Default::default(),
         }
     }
 
-    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
-        let bytes = key.convert_to_bytes();
-        let key_bytes = bytes.as_ref();
-        let key_len = key_bytes.len();
-        let mut current_link = &mut self.root;
-        let mut depth: usize = 0;
-        let mut inner_byte: u8 = Default::default();
-        let mut new_pkey: ByteKey = Vec::new();
-        let mut partial_match = false;
+    pub fn len(&self) -> usize {
+        self.len
+    }
 
-        while let Some(ARTNode::Inner(ref mut inner, ref mut pkey, ref mut val)) = current_link {
-            let pkey_size = pkey.len();
-            let end = (depth + pkey_size as usize).min(key_bytes.len());
-            let current_pkey = &key_bytes[depth..end];
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
 
-            match compare_pkeys(pkey, current_pkey) {
-                PartialKeyComp::FullMatch(len) => {
-                    depth += len;
-                    if depth == key_len {
-                        return val.replace(value);
-                    }
-                    if let Some(link) = inner.find_child_mut(key_bytes[depth]) {
-                        current_link = unsafe { &mut *link };
-                    } else {
-                        break;
-                    }
+    pub fn clear(&mut self) {
+        self.root = None;
+        self.len = 0;
+    }
 
-                    depth += 1;
-                }
-                PartialKeyComp::PartialMatch(len) => {
-                    depth += len;
-                    inner_byte = pkey[len];
-                    new_pkey = pkey.split_off(len + 1);
-                    pkey.pop();
-                    swap(pkey, &mut new_pkey);
-                    partial_match = true;
-                    break;
-                }
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        match self.entry(key) {
+            Entry::Occupied(mut entry) => Some(entry.insert(value)),
+            Entry::Vacant(entry) => {
+                entry.insert(value);
+                None
             }
         }
+    }
 
-        if let Some(node) = current_link.take() {
-            if partial_match {
-                let mut new_inner = ARTInnerNode::new_inner_4();
-                new_inner.add_child(key_bytes[depth + 1..].to_vec(),
-                                    value,
-                                    key_bytes[depth]);
-                new_inner.add_node(node, inner_byte);
-                current_link.replace(ARTNode::Inner(new_inner,
-                                                    new_pkey,
-                                                    None));
-                return None;
-            }
-
-            match node {
-                ARTNode::Inner(inner, pkey, val) => {
-                    let mut inner = if inner.is_full() { inner.grow() } else { inner };
-
-                    inner.add_child(key_bytes[depth + 1..].to_vec(),
-                                    value,
-                                    key_bytes[depth]);
-                    *current_link = Some(ARTNode::Inner(inner, pkey, val));
-                }
-                ARTNode::Leaf(mut leaf) => {
-                    match compare_leaf_keys(leaf.pkey(), &key_bytes[depth..]) {
-                        LeafKeyComp::FullMatch => {
-                            let ret = leaf.change_value(value);
-                            *current_link = Some(ARTNode::Leaf(leaf));
-                            return Some(ret);
-                        }
-                        LeafKeyComp::PartialMatch(len) => {
-                            depth += len;
-                            let mut new_inner = ARTInnerNode::new_inner_4();
-
-                            let byte: u8 = leaf.pkey()[len];
-                            let leaf_pkey = leaf.pkey_mut();
-                            new_pkey = leaf_pkey.split_off(len + 1);
-                            leaf_pkey.pop();
-                            swap(&mut new_pkey, leaf_pkey);
-
-                            new_inner.add_child(key_bytes[depth + 1..].to_vec(),
-                                                value,
-                                                key_bytes[depth]);
-
-                            new_inner.add_node(ARTNode::Leaf(leaf), byte);
-                            *current_link =
-                                Some(ARTNode::Inner(new_inner,
-                                                    new_pkey,
-                                                    None));
-                        }
-                        LeafKeyComp::CompleteMatchLeft(len) => {
-                            depth += len;
-                            let mut new_inner = ARTInnerNode::new_inner_4();
-
-                            new_inner.add_child(key_bytes[depth + 1..].to_vec(),
-                                                value,
-                                                key_bytes[depth]);
-
-                            let (leaf_pkey, leaf_value) = leaf.take_pkey_and_value();
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V> {
+        let search = {
+            let bytes = key.convert_to_bytes();
+            search_mut(&mut self.root, bytes.as_ref())
+        };
 
-                            *current_link = Some(ARTNode::Inner(
-                                new_inner,
-                                leaf_pkey,
-                                Some(leaf_value),
-                            ));
-                        }
-                        LeafKeyComp::CompleteMatchRight(len) => {
-                            let mut new_inner = ARTInnerNode::new_inner_4();
-                            let byte: u8 = leaf.pkey()[len];
-                            let leaf_pkey = leaf.pkey_mut();
-                            new_pkey = leaf_pkey.split_off(len + 1);
-                            leaf_pkey.pop();
-                            swap(&mut new_pkey, leaf_pkey);
-
-                            new_inner.add_node(ARTNode::Leaf(leaf), byte);
-                            *current_link = Some(ARTNode::Inner(
-                                new_inner,
-                                new_pkey,
-                                Some(value),
-                            ));
-                        }
-                    }
-                }
+        match search {
+            Search::Found(link, child) => {
+                Entry::Occupied(OccupiedEntry::new(key, link, child, &mut self.len))
+            }
+            Search::Missing(link, depth, split) => {
+                Entry::Vacant(VacantEntry::new(key, link, depth, split, &mut self.len))
             }
-        } else {
-            *current_link = Some(ARTNode::Leaf(ARTLeaf::new(key_bytes[depth..].to_vec(),
-                                                            value)));
         }
-        None
     }
 
-    pub fn delete(&mut self, key: K) -> Option<V> {
+    pub fn delete<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ARTKey + ?Sized,
+    {
         let bytes = key.convert_to_bytes();
-        let key_bytes = bytes.as_ref();
-        let key_len = key_bytes.len();
-        let mut current_link = &mut self.root;
-        let mut depth: usize = 0;
-
-        while let Some(ARTNode::Inner(ref mut inner, ref pkey, _)) = current_link {
-            let pk_size = pkey.len();
-            let end = (depth + pk_size).min(key_len);
-            let current_pkey = &key_bytes[depth..end];
-
-            if let PartialKeyComp::FullMatch(len) = compare_pkeys(pkey, current_pkey) {
-                depth += len;
-                if depth == key_len {
-                    // key match in inner node
-                    break;
-                }
-
-                let link = inner.find_child_mut(key_bytes[depth])?;
-                let new_link = unsafe { &mut *link };
+        self.delete_encoded(bytes.as_ref())
+    }
 
-                if let Some(ARTNode::Leaf(ref leaf)) = new_link {
-                    if let LeafKeyComp::FullMatch =
-                        compare_leaf_keys(leaf.pkey(), &key_bytes[depth + 1..])
-                    {
-                        break;
-                    } else {
-                        return None;
-                    }
-                } else {
-                    current_link = new_link;
-                    depth += 1;
-                }
-            } else {
-                return None;
+    /// `insert` for a key that is already encoded.
+    pub(crate) fn insert_encoded(&mut self, key_bytes: &[u8], value: V) -> Option<V> {
+        match search_mut(&mut self.root, key_bytes) {
+            Search::Found(link, child) => Some(std::mem::replace(value_at(link.into_node(), child), value)),
+            Search::Missing(mut link, depth, split) => {
+                insert_at(&mut link, key_bytes, depth, split, value);
+                self.len += 1;
+                None
             }
         }
+    }
 
-        match current_link.take()? {
-            ARTNode::Inner(mut inner, pkey, val) => {
-                if depth == key_len {
-                    // shrink needed?
-                    *current_link = Some(ARTNode::Inner(inner, pkey, None));
-                    return val;
-                }
-
-                let former_val = inner.remove_child(key_bytes[depth]);
-
-                let new_inner = if inner.is_shrinkable() {
-                    inner.shrink()
-                } else {
-                    inner
-                };
-
-                *current_link = Some(ARTNode::Inner(new_inner, pkey, val));
-                former_val
-            }
-            ARTNode::Leaf(leaf) => {
-                // only if tree consists only of one leaf node
-                let leaf_comp = compare_leaf_keys(leaf.pkey(), &key_bytes);
-                if let LeafKeyComp::FullMatch = leaf_comp {
-                    Some(leaf.take_value())
-                } else {
-                    *current_link = Some(ARTNode::Leaf(leaf));
-                    None
-                }
+    /// `delete` for a key that is already encoded.
+    pub(crate) fn delete_encoded(&mut self, key_bytes: &[u8]) -> Option<V> {
+        match search_mut(&mut self.root, key_bytes) {
+            Search::Found(mut link, child) => {
+                self.len -= 1;
+                Some(remove_at(&mut link, child))
             }
+            Search::Missing(..) => None,
         }
     }
 
-    pub fn get(&self, key: K) -> Option<&V> {
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: ARTKey + ?Sized,
+    {
         let key_bytes = key.convert_to_bytes();
         let key_bytes = key_bytes.as_ref();
         let key_len = key_bytes.len();
@@ -258,4 +138,550 @@ impl<K: ARTKey, V> ARTree<K, V> {
         }
         None
     }
+
+    /// Iterates in key order over all entries whose encoded key starts with
+    /// the encoding of `prefix`.
+    pub fn prefix_iter<P: ARTKey + ?Sized>(&self, prefix: &P) -> Iter<'_, K, V> {
+        let bytes = prefix.convert_to_bytes();
+        let prefix_bytes = bytes.as_ref();
+        let mut current = self.root.as_ref();
+        let mut depth: usize = 0;
+
+        while let Some(node) = current {
+            let rest = &prefix_bytes[depth..];
+            let pkey = node.pkey();
+
+            // the prefix ends inside this node's compressed prefix
+            if rest.len() <= pkey.len() {
+                if pkey.starts_with(rest) {
+                    return Iter::new_at(Some(node), prefix_bytes[..depth].to_vec());
+                }
+                break;
+            }
+
+            match node {
+                ARTNode::Inner(inner, pkey, _) if rest.starts_with(pkey) => {
+                    depth += pkey.len();
+                    current = inner.find_child(prefix_bytes[depth]);
+                    depth += 1;
+                }
+                _ => break,
+            }
+        }
+
+        Iter::new_at(None, Vec::new())
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: ARTKey + ?Sized,
+    {
+        let bytes = key.convert_to_bytes();
+
+        match search_mut(&mut self.root, bytes.as_ref()) {
+            Search::Found(link, child) => Some(value_at(link.into_node(), child)),
+            Search::Missing(..) => None,
+        }
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ARTKey + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter::new(self.root.as_ref())
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        IterMut::new(self.root.as_mut())
+    }
+
+    pub fn range<T, R>(&self, range: R) -> Range<'_, K, V>
+    where
+        K: Borrow<T>,
+        T: ARTKey + ?Sized,
+        R: RangeBounds<T>,
+    {
+        let (lower, upper) = encode_bounds(range);
+        Range::new(self.root.as_ref(), lower, upper)
+    }
+
+    pub fn range_mut<T, R>(&mut self, range: R) -> RangeMut<'_, K, V>
+    where
+        K: Borrow<T>,
+        T: ARTKey + ?Sized,
+        R: RangeBounds<T>,
+    {
+        let (lower, upper) = encode_bounds(range);
+        RangeMut::new(self.root.as_mut(), lower, upper)
+    }
+
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        Keys::new(self.iter())
+    }
+
+    pub fn values(&self) -> Values<'_, K, V> {
+        Values::new(self.iter())
+    }
+
+    /// Entries in key order with their keys left encoded, so comparing and
+    /// hashing trees does not depend on `K` being decodable.
+    fn encoded_entries(&self) -> impl Iterator<Item = (ByteKey, &V)> + '_ {
+        let mut raw = RawIter::new(self.root.as_ref());
+        std::iter::from_fn(move || raw.next())
+    }
+}
+
+impl<K: ARTKey, V: Clone> Clone for ARTree<K, V> {
+    fn clone(&self) -> Self {
+        ARTree {
+            root: self.root.clone(),
+            len: self.len,
+            _marker: Default::default(),
+        }
+    }
+}
+
+impl<K: ARTKey, V: PartialEq> PartialEq for ARTree<K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.encoded_entries().eq(other.encoded_entries())
+    }
+}
+
+impl<K: ARTKey, V: Eq> Eq for ARTree<K, V> {}
+
+impl<K: ARTKey, V: Hash> Hash for ARTree<K, V> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_usize(self.len);
+        for entry in self.encoded_entries() {
+            entry.hash(state);
+        }
+    }
+}
+
+impl<K: ARTKeyDecode + Debug, V: Debug> Debug for ARTree<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K, Q, V> Index<&Q> for ARTree<K, V>
+where
+    K: ARTKey + Borrow<Q>,
+    Q: ARTKey + ?Sized,
+{
+    type Output = V;
+
+    /// Panics if the key is not present in the tree.
+    fn index(&self, key: &Q) -> &V {
+        self.get(key).expect("no entry found for key")
+    }
+}
+
+impl<K: ARTKey, V> FromIterator<(K, V)> for ARTree<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut tree = ARTree::new();
+        tree.extend(iter);
+        tree
+    }
+}
+
+impl<K: ARTKey, V> Extend<(K, V)> for ARTree<K, V> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl<'a, K: ARTKey + Copy, V: Copy> Extend<(&'a K, &'a V)> for ARTree<K, V> {
+    fn extend<I: IntoIterator<Item = (&'a K, &'a V)>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().map(|(&key, &value)| (key, value)));
+    }
+}
+
+impl<K: ARTKeyDecode, V> IntoIterator for ARTree<K, V> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+
+    fn into_iter(self) -> IntoIter<K, V> {
+        IntoIter::new(self.root)
+    }
+}
+
+impl<'a, K: ARTKeyDecode, V> IntoIterator for &'a ARTree<K, V> {
+    type Item = (K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+impl<'a, K: ARTKeyDecode, V> IntoIterator for &'a mut ARTree<K, V> {
+    type Item = (K, &'a mut V);
+    type IntoIter = IterMut<'a, K, V>;
+
+    fn into_iter(self) -> IterMut<'a, K, V> {
+        self.iter_mut()
+    }
+}
+
+/// Encodes the bounds of a key range, panicking on the same inverted ranges
+/// as `BTreeMap::range`.
+pub(crate) fn encode_bounds<T, R>(range: R) -> (Bound<ByteKey>, Bound<ByteKey>)
+where
+    T: ARTKey + ?Sized,
+    R: RangeBounds<T>,
+{
+    let encode = |key: &T| key.convert_to_bytes().as_ref().to_vec();
+    let lower = range.start_bound().map(encode);
+    let upper = range.end_bound().map(encode);
+
+    match (&lower, &upper) {
+        (Bound::Included(start) | Bound::Excluded(start),
+         Bound::Included(end) | Bound::Excluded(end)) if start > end => {
+            panic!("range start is greater than range end")
+        }
+        (Bound::Excluded(start), Bound::Excluded(end)) if start == end => {
+            panic!("range start and end are equal and excluded")
+        }
+        _ => {}
+    }
+
+    (lower, upper)
+}
+
+/// Where a node hangs: the tree's root link, or the slot of an inner node
+/// under a key byte. A node taken out of a child slot goes through
+/// `take_child`, so the parent's child count always matches its slots.
+pub(crate) enum Slot<'a, V> {
+    Root(&'a mut ARTLink<V>),
+    Child(&'a mut ARTInnerNode<ARTNode<V>>, u8),
+}
+
+impl<'a, V> Slot<'a, V> {
+    pub(crate) fn node(&self) -> Option<&ARTNode<V>> {
+        match self {
+            Slot::Root(link) => link.as_ref(),
+            Slot::Child(inner, byte) => inner.find_child(*byte),
+        }
+    }
+
+    pub(crate) fn node_mut(&mut self) -> Option<&mut ARTNode<V>> {
+        match self {
+            Slot::Root(link) => link.as_mut(),
+            Slot::Child(inner, byte) => inner.find_child_mut(*byte),
+        }
+    }
+
+    pub(crate) fn into_node(self) -> Option<&'a mut ARTNode<V>> {
+        match self {
+            Slot::Root(link) => link.as_mut(),
+            Slot::Child(inner, byte) => inner.find_child_mut(byte),
+        }
+    }
+
+    fn take(&mut self) -> Option<ARTNode<V>> {
+        match self {
+            Slot::Root(link) => link.take(),
+            Slot::Child(inner, byte) => inner.take_child(*byte),
+        }
+    }
+
+    /// Puts a node into the slot emptied by `take`.
+    fn put(&mut self, node: ARTNode<V>) {
+        match self {
+            Slot::Root(link) => **link = Some(node),
+            Slot::Child(inner, byte) => inner.add_node(node, *byte),
+        }
+    }
+}
+
+/// Outcome of a mutable descent.
+pub(crate) enum Search<'a, V> {
+    /// The value lives in the node in the slot itself, or in its leaf child
+    /// under the given key byte.
+    Found(Slot<'a, V>, Option<u8>),
+    /// The key is missing. `depth` counts the key bytes consumed above the
+    /// slot; `split` is the length of the matching part of the node's
+    /// compressed prefix if the key diverges from it, or ends inside it.
+    Missing(Slot<'a, V>, usize, Option<usize>),
+}
+
+/// What one step of `search_mut` decided after inspecting a node.
+enum Step {
+    Found(Option<u8>),
+    Missing(Option<usize>),
+    Descend(u8, usize),
+}
+
+pub(crate) fn search_mut<'a, V>(root: &'a mut ARTLink<V>, key_bytes: &[u8]) -> Search<'a, V> {
+    let key_len = key_bytes.len();
+    let mut current = Slot::Root(root);
+    let mut depth: usize = 0;
+
+    loop {
+        // Decide through a shared borrow first, so the exclusive borrow of
+        // `current` is only split when the descent actually moves on.
+        let step = match current.node() {
+            Some(ARTNode::Inner(inner, pkey, val)) => {
+                let pkey_size = pkey.len();
+                let end = (depth + pkey_size).min(key_len);
+
+                match compare_pkeys(pkey, &key_bytes[depth..end]) {
+                    PartialKeyComp::FullMatch(len) if len == pkey_size => {
+                        let inner_depth = depth + pkey_size;
+
+                        if inner_depth == key_len {
+                            if val.is_some() { Step::Found(None) } else { Step::Missing(None) }
+                        } else {
+                            let byte = key_bytes[inner_depth];
+                            match inner.find_child(byte) {
+                                Some(ARTNode::Leaf(leaf)) if leaf.pkey() == &key_bytes[inner_depth + 1..] => {
+                                    Step::Found(Some(byte))
+                                }
+                                Some(_) => Step::Descend(byte, inner_depth + 1),
+                                None => Step::Missing(None),
+                            }
+                        }
+                    }
+                    PartialKeyComp::FullMatch(len) | PartialKeyComp::PartialMatch(len) => {
+                        Step::Missing(Some(len))
+                    }
+                }
+            }
+            Some(ARTNode::Leaf(leaf)) => {
+                if leaf.pkey() == &key_bytes[depth..] { Step::Found(None) } else { Step::Missing(None) }
+            }
+            None => Step::Missing(None),
+        };
+
+        match step {
+            Step::Found(child) => return Search::Found(current, child),
+            Step::Missing(split) => return Search::Missing(current, depth, split),
+            Step::Descend(byte, child_depth) => {
+                current = match current.into_node() {
+                    Some(ARTNode::Inner(inner, _, _)) => Slot::Child(inner, byte),
+                    _ => unreachable!("only inner nodes are descended into"),
+                };
+                depth = child_depth;
+            }
+        }
+    }
+}
+
+/// Returns the value located by `Search::Found`.
+pub(crate) fn value_at<V>(node: Option<&mut ARTNode<V>>, child: Option<u8>) -> &mut V {
+    match (node.expect("value location points to an empty slot"), child) {
+        (ARTNode::Leaf(leaf), None) => leaf.value_mut(),
+        (ARTNode::Inner(_, _, val), None) => val.as_mut().expect("inner node has no value"),
+        (ARTNode::Inner(inner, _, _), Some(byte)) => {
+            match inner.find_child_mut(byte) {
+                Some(ARTNode::Leaf(leaf)) => leaf.value_mut(),
+                _ => unreachable!("value location points to an inner child"),
+            }
+        }
+        (ARTNode::Leaf(_), Some(_)) => unreachable!("leaves have no children"),
+    }
+}
+
+pub(crate) fn value_ref_at<V>(node: Option<&ARTNode<V>>, child: Option<u8>) -> &V {
+    match (node.expect("value location points to an empty slot"), child) {
+        (ARTNode::Leaf(leaf), None) => leaf.value(),
+        (ARTNode::Inner(_, _, val), None) => val.as_ref().expect("inner node has no value"),
+        (ARTNode::Inner(inner, _, _), Some(byte)) => match inner.find_child(byte) {
+            Some(ARTNode::Leaf(leaf)) => leaf.value(),
+            _ => unreachable!("value location points to an inner child"),
+        },
+        (ARTNode::Leaf(_), Some(_)) => unreachable!("leaves have no children"),
+    }
+}
+
+/// Finishes an insert at the place found by `Search::Missing` and returns
+/// where the new value ended up, in the form used by `value_at`.
+pub(crate) fn insert_at<V>(link: &mut Slot<'_, V>,
+                           key_bytes: &[u8],
+                           mut depth: usize,
+                           split: Option<usize>,
+                           value: V) -> Option<u8> {
+    let key_len = key_bytes.len();
+
+    // a value or a child added to an inner node that keeps its prefix and
+    // has room changes the node in place
+    if let (Some(ARTNode::Inner(inner, pkey, val)), None) = (link.node_mut(), split) {
+        let inner_depth = depth + pkey.len();
+
+        if inner_depth == key_len {
+            *val = Some(value);
+            return None;
+        }
+        if !inner.is_full() {
+            inner.add_child(key_bytes[inner_depth + 1..].to_vec(), value, key_bytes[inner_depth]);
+            return Some(key_bytes[inner_depth]);
+        }
+    }
+
+    let node = match link.take() {
+        Some(node) => node,
+        None => {
+            link.put(ARTNode::Leaf(ARTLeaf::new(key_bytes[depth..].to_vec(), value)));
+            return None;
+        }
+    };
+
+    match node {
+        ARTNode::Inner(inner, mut pkey, val) => {
+            if let Some(len) = split {
+                // the key either diverges from the prefix or ends inside it
+                depth += len;
+                let inner_byte = pkey[len];
+                let inner_pkey = pkey.split_off(len + 1);
+                pkey.pop();
+
+                let mut new_inner = ARTInnerNode::new_inner_4();
+                new_inner.add_node(ARTNode::Inner(inner, inner_pkey, val), inner_byte);
+
+                if depth == key_len {
+                    link.put(ARTNode::Inner(new_inner, pkey, Some(value)));
+                    return None;
+                }
+
+                new_inner.add_child(key_bytes[depth + 1..].to_vec(), value, key_bytes[depth]);
+                link.put(ARTNode::Inner(new_inner, pkey, None));
+                return Some(key_bytes[depth]);
+            }
+
+            depth += pkey.len();
+            if depth == key_len {
+                link.put(ARTNode::Inner(inner, pkey, Some(value)));
+                return None;
+            }
+
+            let mut inner = if inner.is_full() { inner.grow() } else { inner };
+
+            inner.add_child(key_bytes[depth + 1..].to_vec(),
+                            value,
+                            key_bytes[depth]);
+            link.put(ARTNode::Inner(inner, pkey, val));
+            Some(key_bytes[depth])
+        }
+        ARTNode::Leaf(mut leaf) => {
+            match compare_leaf_keys(leaf.pkey(), &key_bytes[depth..]) {
+                LeafKeyComp::FullMatch => {
+                    unreachable!("existing keys are found before inserting")
+                }
+                LeafKeyComp::PartialMatch(len) => {
+                    depth += len;
+                    let mut new_inner = ARTInnerNode::new_inner_4();
+
+                    let byte: u8 = leaf.pkey()[len];
+                    let leaf_pkey = leaf.pkey_mut();
+                    let mut new_pkey = leaf_pkey.split_off(len + 1);
+                    leaf_pkey.pop();
+                    swap(&mut new_pkey, leaf_pkey);
+
+                    new_inner.add_child(key_bytes[depth + 1..].to_vec(),
+                                        value,
+                                        key_bytes[depth]);
+
+                    new_inner.add_node(ARTNode::Leaf(leaf), byte);
+                    link.put(ARTNode::Inner(new_inner,
+                                                new_pkey,
+                                                None));
+                    Some(key_bytes[depth])
+                }
+                LeafKeyComp::CompleteMatchLeft(len) => {
+                    depth += len;
+                    let mut new_inner = ARTInnerNode::new_inner_4();
+
+                    new_inner.add_child(key_bytes[depth + 1..].to_vec(),
+                                        value,
+                                        key_bytes[depth]);
+
+                    let (leaf_pkey, leaf_value) = leaf.take_pkey_and_value();
+
+                    link.put(ARTNode::Inner(
+                        new_inner,
+                        leaf_pkey,
+                        Some(leaf_value),
+                    ));
+                    Some(key_bytes[depth])
+                }
+                LeafKeyComp::CompleteMatchRight(len) => {
+                    let mut new_inner = ARTInnerNode::new_inner_4();
+                    let byte: u8 = leaf.pkey()[len];
+                    let leaf_pkey = leaf.pkey_mut();
+                    let mut new_pkey = leaf_pkey.split_off(len + 1);
+                    leaf_pkey.pop();
+                    swap(&mut new_pkey, leaf_pkey);
+
+                    new_inner.add_node(ARTNode::Leaf(leaf), byte);
+                    link.put(ARTNode::Inner(
+                        new_inner,
+                        new_pkey,
+                        Some(value),
+                    ));
+                    None
+                }
+            }
+        }
+    }
+}
+
+/// Removes the value located by `Search::Found`.
+pub(crate) fn remove_at<V>(link: &mut Slot<'_, V>, child: Option<u8>) -> V {
+    match (link.take().expect("value location points to an empty slot"), child) {
+        (ARTNode::Leaf(leaf), None) => leaf.take_value(),
+        (ARTNode::Inner(inner, pkey, val), None) => {
+            if let Some(node) = compact(inner, pkey, None) {
+                link.put(node);
+            }
+            val.expect("inner node has no value")
+        }
+        (ARTNode::Inner(mut inner, pkey, val), Some(byte)) => {
+            // values of inner children are located through the child's own
+            // link, so only leaves can be removed from their parent here
+            let former_val = match inner.take_child(byte) {
+                Some(ARTNode::Leaf(leaf)) => leaf.take_value(),
+                _ => unreachable!("value location points to a missing or inner child"),
+            };
+            if let Some(node) = compact(inner, pkey, val) {
+                link.put(node);
+            }
+            former_val
+        }
+        (ARTNode::Leaf(_), Some(_)) => unreachable!("leaves have no children"),
+    }
+}
+
+/// Restores the ART invariants of an inner node that lost its value or a
+/// child: nodes without children are freed or turned back into leaves, and
+/// a node left with a single child and no value is merged into that child by
+/// concatenating the prefixes.
+fn compact<V>(mut inner: ARTInnerNode<ARTNode<V>>, pkey: ByteKey, val: Option<V>) -> ARTLink<V> {
+    match (inner.children_num(), val) {
+        (0, None) => None,
+        (0, Some(val)) => Some(ARTNode::Leaf(ARTLeaf::new(pkey, val))),
+        (1, None) => {
+            let (byte, _) = inner.sorted_children()[0];
+            let mut child = inner.take_child(byte).expect("missing only child");
+            child.prepend_pkey(pkey, byte);
+            Some(child)
+        }
+        (_, val) => {
+            let inner = if inner.is_shrinkable() {
+                inner.shrink()
+            } else {
+                inner
+            };
+
+            Some(ARTNode::Inner(inner, pkey, val))
+        }
+    }
 }